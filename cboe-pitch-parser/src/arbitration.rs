@@ -0,0 +1,500 @@
+use crate::message::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitratorConfig {
+    pub line_count: usize,         // Redundant lines carrying every unit (A, B, ...)
+    pub gap_timeout: Duration,     // How long to wait for another line to fill a gap
+    pub max_pending_frames: usize, // Frames buffered behind a gap before giving up
+}
+
+impl ArbitratorConfig {
+    pub fn new(line_count: usize) -> Self {
+        Self {
+            line_count,
+            gap_timeout: Duration::from_millis(50),
+            max_pending_frames: 1024,
+        }
+    }
+}
+
+/// Output of the arbitrator, in sequence order per unit
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArbitratedEvent {
+    Messages {
+        unit: u8,
+        sequence: u32, // Sequence of the first message
+        line: usize,   // Line whose copy was used
+        messages: Vec<PitchMessage>,
+    },
+    Gap {
+        unit: u8,
+        sequence: u32, // First missing sequence
+        count: u32,
+    },
+}
+
+/// Per-line arbitration counters, in messages
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineStats {
+    pub frames: u64,
+    pub messages: u64,
+    pub won: u64,        // Delivered first and used
+    pub duplicates: u64, // Already delivered by another line
+    pub lost: u64,       // Skipped in this line's own sequence stream
+}
+
+impl LineStats {
+    pub fn win_rate(&self) -> f64 {
+        if self.messages == 0 {
+            0.0
+        } else {
+            self.won as f64 / self.messages as f64
+        }
+    }
+    
+    pub fn loss_rate(&self) -> f64 {
+        let expected = self.messages + self.lost;
+        if expected == 0 {
+            0.0
+        } else {
+            self.lost as f64 / expected as f64
+        }
+    }
+}
+
+/// Per-unit arbitration counters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnitArbitrationStats {
+    pub messages: u64,
+    pub gaps: u64,
+    pub gap_messages: u64,
+    pub filled_gaps: u64, // Gaps closed by a late frame instead of being declared
+}
+
+#[derive(Debug)]
+struct PendingFrame {
+    line: usize,
+    messages: Vec<PitchMessage>,
+}
+
+#[derive(Debug, Default)]
+struct UnitState {
+    next_sequence: Option<u32>,
+    pending: BTreeMap<u32, PendingFrame>,
+    line_next: Vec<Option<u32>>, // Next sequence expected on each line
+    highest: Option<u32>,        // One past the last sequence any line has shown, heartbeats included
+    gap_since: Option<Instant>,
+    stats: UnitArbitrationStats,
+}
+
+/// Merges redundant feed lines so every sequence of a unit is emitted exactly once
+#[derive(Debug)]
+pub struct FeedArbitrator {
+    config: ArbitratorConfig,
+    units: HashMap<u8, UnitState>,
+    line_stats: Vec<LineStats>,
+}
+
+impl FeedArbitrator {
+    pub fn new(config: ArbitratorConfig) -> Self {
+        let line_stats = vec![LineStats::default(); config.line_count];
+        Self {
+            config,
+            units: HashMap::new(),
+            line_stats,
+        }
+    }
+    
+    /// Feed one frame received on `line`; returns everything that became deliverable
+    pub fn process(
+        &mut self,
+        line: usize,
+        header: &SequencedUnitHeader,
+        messages: Vec<PitchMessage>,
+        now: Instant,
+    ) -> Vec<ArbitratedEvent> {
+        if line >= self.line_stats.len() {
+            self.line_stats.resize(line + 1, LineStats::default());
+        }
+        
+        let line_count = self.line_stats.len();
+        let unit = header.unit;
+        let state = self.units.entry(unit).or_default();
+        if state.line_next.len() < line_count {
+            state.line_next.resize(line_count, None);
+        }
+        
+        let count = messages.len() as u32;
+        let end = header.sequence.wrapping_add(count);
+        let stats = &mut self.line_stats[line];
+        stats.frames += 1;
+        stats.messages += count as u64;
+        
+        match state.line_next[line] {
+            Some(expected) if header.sequence > expected => {
+                stats.lost += (header.sequence - expected) as u64;
+                state.line_next[line] = Some(end);
+            },
+            Some(expected) if end > expected => state.line_next[line] = Some(end),
+            None => state.line_next[line] = Some(end),
+            _ => {}
+        }
+        if state.highest.is_none_or(|highest| end > highest) {
+            state.highest = Some(end);
+        }
+        
+        state.next_sequence.get_or_insert(header.sequence);
+        Self::admit(state, &mut self.line_stats[line], line, header.sequence, messages);
+        
+        let mut events = Vec::new();
+        Self::drain(state, &mut self.line_stats, &self.config, unit, now, &mut events);
+        events
+    }
+    
+    /// Declare gaps whose fill timeout has expired even when no new frames arrive
+    pub fn poll(&mut self, now: Instant) -> Vec<ArbitratedEvent> {
+        let mut events = Vec::new();
+        
+        for (&unit, state) in self.units.iter_mut() {
+            Self::drain(state, &mut self.line_stats, &self.config, unit, now, &mut events);
+        }
+        
+        events
+    }
+    
+    /// Buffer the parts of a frame no line has delivered yet, so each sequence is
+    /// credited to the line it arrived on first; the rest count as duplicates
+    fn admit(state: &mut UnitState, stats: &mut LineStats, line: usize, sequence: u32, mut messages: Vec<PitchMessage>) {
+        let mut start = sequence;
+        let next = state.next_sequence.unwrap_or(sequence);
+        let delivered = next.saturating_sub(start).min(messages.len() as u32);
+        stats.duplicates += delivered as u64;
+        messages.drain(..delivered as usize);
+        start += delivered;
+        
+        while !messages.is_empty() {
+            // Pending ranges never overlap, so only the one starting at or before `start` can cover it
+            let covering = state.pending
+                .range(..=start)
+                .next_back()
+                .map(|(&first, pending)| first + pending.messages.len() as u32)
+                .filter(|&end| end > start);
+            if let Some(end) = covering {
+                let covered = (end - start).min(messages.len() as u32);
+                stats.duplicates += covered as u64;
+                messages.drain(..covered as usize);
+                start += covered;
+                continue;
+            }
+            
+            let uncovered = state.pending
+                .range(start..)
+                .next()
+                .map_or(messages.len() as u32, |(&first, _)| (first - start).min(messages.len() as u32));
+            let rest = messages.split_off(uncovered as usize);
+            state.pending.insert(start, PendingFrame { line, messages });
+            messages = rest;
+            start += uncovered;
+        }
+    }
+    
+    fn deliver(
+        state: &mut UnitState,
+        line_stats: &mut [LineStats],
+        unit: u8,
+        line: usize,
+        sequence: u32,
+        mut messages: Vec<PitchMessage>,
+        events: &mut Vec<ArbitratedEvent>,
+    ) {
+        let next = state.next_sequence.unwrap_or(sequence);
+        let skip = (next - sequence).min(messages.len() as u32) as usize;
+        let stats = &mut line_stats[line];
+        stats.duplicates += skip as u64;
+        
+        if skip == messages.len() {
+            return;
+        }
+        
+        messages.drain(..skip);
+        let delivered = messages.len() as u32;
+        stats.won += delivered as u64;
+        state.stats.messages += delivered as u64;
+        state.next_sequence = Some(next + delivered);
+        
+        events.push(ArbitratedEvent::Messages {
+            unit,
+            sequence: next,
+            line,
+            messages,
+        });
+    }
+    
+    fn drain(
+        state: &mut UnitState,
+        line_stats: &mut [LineStats],
+        config: &ArbitratorConfig,
+        unit: u8,
+        now: Instant,
+        events: &mut Vec<ArbitratedEvent>,
+    ) {
+        loop {
+            let Some(next) = state.next_sequence else {
+                return;
+            };
+            
+            // With nothing buffered, a heartbeat may still have shown the stream is behind
+            let first = state.pending
+                .first_key_value()
+                .map(|(&first, _)| first)
+                .or(state.highest.filter(|&highest| highest > next));
+            let Some(first) = first else {
+                state.gap_since = None;
+                return;
+            };
+            
+            if first <= next {
+                // A gap closed by a frame rather than declared was filled; either way the
+                // fill timeout restarts whenever the stream advances
+                if state.gap_since.take().is_some() {
+                    state.stats.filled_gaps += 1;
+                }
+                let pending = state.pending.remove(&first).unwrap();
+                Self::deliver(state, line_stats, unit, pending.line, first, pending.messages, events);
+                continue;
+            }
+            
+            // The gap is real once every line has moved past it, or waiting is no longer worthwhile
+            let since = *state.gap_since.get_or_insert(now);
+            let all_lines_past = state.line_next.iter().all(|line_next| line_next.is_some_and(|seq| seq > next));
+            let timed_out = now.duration_since(since) >= config.gap_timeout;
+            let overflowed = state.pending.len() > config.max_pending_frames;
+            
+            if !(all_lines_past || timed_out || overflowed) {
+                return;
+            }
+            
+            let count = first - next;
+            state.stats.gaps += 1;
+            state.stats.gap_messages += count as u64;
+            state.next_sequence = Some(first);
+            state.gap_since = None;
+            events.push(ArbitratedEvent::Gap {
+                unit,
+                sequence: next,
+                count,
+            });
+        }
+    }
+    
+    pub fn next_sequence(&self, unit: u8) -> Option<u32> {
+        self.units.get(&unit).and_then(|state| state.next_sequence)
+    }
+    
+    pub fn line_stats(&self, line: usize) -> Option<&LineStats> {
+        self.line_stats.get(line)
+    }
+    
+    pub fn unit_stats(&self, unit: u8) -> Option<&UnitArbitrationStats> {
+        self.units.get(&unit).map(|state| &state.stats)
+    }
+    
+    /// Forget all sequence state for a unit, e.g. after a Unit Clear
+    pub fn reset_unit(&mut self, unit: u8) {
+        self.units.remove(&unit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    
+    fn header(sequence: u32, count: usize) -> SequencedUnitHeader {
+        SequencedUnitHeader { length: 0, count: count as u8, unit: 1, sequence }
+    }
+    
+    fn deletes(first_order: u64, count: usize) -> Vec<PitchMessage> {
+        (0..count as u64)
+            .map(|i| PitchMessage::DeleteOrder {
+                timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000),
+                order_id: OrderId(first_order + i),
+            })
+            .collect()
+    }
+    
+    fn gaps(events: &[ArbitratedEvent]) -> Vec<(u32, u32)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                ArbitratedEvent::Gap { sequence, count, .. } => Some((*sequence, *count)),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn duplicate_from_second_line_is_dropped() {
+        let mut arbitrator = FeedArbitrator::new(ArbitratorConfig::new(2));
+        let now = Instant::now();
+        
+        let events = arbitrator.process(0, &header(1, 3), deletes(1, 3), now);
+        assert!(matches!(&events[..], [ArbitratedEvent::Messages { sequence: 1, line: 0, messages, .. }] if messages.len() == 3));
+        
+        assert!(arbitrator.process(1, &header(1, 3), deletes(1, 3), now).is_empty());
+        assert_eq!(arbitrator.next_sequence(1), Some(4));
+        assert_eq!(arbitrator.line_stats(0).unwrap().won, 3);
+        assert_eq!(arbitrator.line_stats(1).unwrap().duplicates, 3);
+    }
+    
+    #[test]
+    fn overlapping_frame_delivers_only_the_new_part() {
+        let mut arbitrator = FeedArbitrator::new(ArbitratorConfig::new(2));
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 2), deletes(1, 2), now);
+        let events = arbitrator.process(1, &header(1, 4), deletes(1, 4), now);
+        
+        assert!(matches!(&events[..], [ArbitratedEvent::Messages { sequence: 3, line: 1, messages, .. }] if messages.len() == 2));
+        assert_eq!(arbitrator.line_stats(1).unwrap().duplicates, 2);
+        assert_eq!(arbitrator.next_sequence(1), Some(5));
+    }
+    
+    #[test]
+    fn gap_on_one_line_is_filled_from_the_other() {
+        let mut arbitrator = FeedArbitrator::new(ArbitratorConfig::new(2));
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 2), deletes(1, 2), now);
+        arbitrator.process(1, &header(1, 2), deletes(1, 2), now);
+        // Line 0 loses 3..4 and runs ahead
+        assert!(arbitrator.process(0, &header(5, 2), deletes(5, 2), now).is_empty());
+        
+        let events = arbitrator.process(1, &header(3, 2), deletes(3, 2), now);
+        assert!(gaps(&events).is_empty());
+        assert!(matches!(&events[..], [
+            ArbitratedEvent::Messages { sequence: 3, line: 1, .. },
+            ArbitratedEvent::Messages { sequence: 5, line: 0, .. },
+        ]));
+        assert_eq!(arbitrator.next_sequence(1), Some(7));
+        assert_eq!(arbitrator.unit_stats(1).unwrap().filled_gaps, 1);
+        assert_eq!(arbitrator.line_stats(0).unwrap().lost, 2);
+    }
+    
+    #[test]
+    fn overlapping_fill_credits_the_line_that_arrived_first() {
+        let mut arbitrator = FeedArbitrator::new(ArbitratorConfig::new(2));
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 2), deletes(1, 2), now);
+        arbitrator.process(1, &header(1, 2), deletes(1, 2), now);
+        arbitrator.process(0, &header(5, 2), deletes(5, 2), now);
+        
+        // Line 1 covers the gap and the frame line 0 already delivered
+        let events = arbitrator.process(1, &header(3, 4), deletes(3, 4), now);
+        assert!(matches!(&events[..], [
+            ArbitratedEvent::Messages { sequence: 3, line: 1, messages: first, .. },
+            ArbitratedEvent::Messages { sequence: 5, line: 0, messages: second, .. },
+        ] if first.len() == 2 && second.len() == 2));
+        
+        let (leading, lagging) = (arbitrator.line_stats(0).unwrap(), arbitrator.line_stats(1).unwrap());
+        assert_eq!((leading.won, leading.duplicates), (4, 0));
+        assert_eq!((lagging.won, lagging.duplicates), (2, 4));
+    }
+    
+    #[test]
+    fn frame_extending_past_pending_delivers_its_tail() {
+        let mut arbitrator = FeedArbitrator::new(ArbitratorConfig::new(2));
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 1), deletes(1, 1), now);
+        arbitrator.process(0, &header(3, 1), deletes(3, 1), now);
+        let events = arbitrator.process(1, &header(1, 5), deletes(1, 5), now);
+        
+        let delivered: Vec<(u32, usize)> = events
+            .iter()
+            .map(|event| match event {
+                ArbitratedEvent::Messages { sequence, line, .. } => (*sequence, *line),
+                ArbitratedEvent::Gap { .. } => panic!("unexpected gap"),
+            })
+            .collect();
+        assert_eq!(delivered, vec![(2, 1), (3, 0), (4, 1)]);
+        assert_eq!(arbitrator.next_sequence(1), Some(6));
+        assert_eq!(arbitrator.line_stats(1).unwrap().won, 3);
+    }
+    
+    #[test]
+    fn partial_fill_restarts_the_gap_timeout() {
+        let config = ArbitratorConfig::new(2);
+        let timeout = config.gap_timeout;
+        let mut arbitrator = FeedArbitrator::new(config);
+        let start = Instant::now();
+        
+        arbitrator.process(0, &header(1, 1), deletes(1, 1), start);
+        arbitrator.process(0, &header(3, 1), deletes(3, 1), start);
+        arbitrator.process(0, &header(6, 1), deletes(6, 1), start);
+        
+        // Line 1 fills 2 late; 4 and 5 are still missing
+        let filled_at = start + timeout * 3 / 4;
+        arbitrator.process(1, &header(2, 1), deletes(2, 1), filled_at);
+        assert_eq!(arbitrator.next_sequence(1), Some(4));
+        assert_eq!(arbitrator.unit_stats(1).unwrap().filled_gaps, 1);
+        
+        assert!(arbitrator.poll(start + timeout).is_empty());
+        assert_eq!(gaps(&arbitrator.poll(filled_at + timeout)), vec![(4, 2)]);
+        assert_eq!(arbitrator.next_sequence(1), Some(7));
+    }
+    
+    #[test]
+    fn gap_missing_on_every_line_is_declared_at_once() {
+        let mut arbitrator = FeedArbitrator::new(ArbitratorConfig::new(2));
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 2), deletes(1, 2), now);
+        arbitrator.process(1, &header(1, 2), deletes(1, 2), now);
+        assert!(arbitrator.process(0, &header(6, 1), deletes(6, 1), now).is_empty());
+        
+        let events = arbitrator.process(1, &header(6, 1), deletes(6, 1), now);
+        assert_eq!(gaps(&events), vec![(3, 3)]);
+        assert!(matches!(events.last(), Some(ArbitratedEvent::Messages { sequence: 6, line: 0, .. })));
+        assert_eq!(arbitrator.line_stats(1).unwrap().duplicates, 3);
+        assert_eq!(arbitrator.unit_stats(1).unwrap().gap_messages, 3);
+    }
+    
+    #[test]
+    fn gap_is_declared_after_timeout() {
+        let config = ArbitratorConfig::new(2);
+        let timeout = config.gap_timeout;
+        let mut arbitrator = FeedArbitrator::new(config);
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 2), deletes(1, 2), now);
+        arbitrator.process(0, &header(5, 1), deletes(5, 1), now);
+        assert!(arbitrator.poll(now + timeout / 2).is_empty());
+        
+        let events = arbitrator.poll(now + timeout);
+        assert_eq!(gaps(&events), vec![(3, 2)]);
+        assert!(matches!(events.last(), Some(ArbitratedEvent::Messages { sequence: 5, .. })));
+        assert_eq!(arbitrator.next_sequence(1), Some(6));
+    }
+    
+    #[test]
+    fn gap_shown_only_by_heartbeat_is_declared_after_timeout() {
+        let config = ArbitratorConfig::new(2);
+        let timeout = config.gap_timeout;
+        let mut arbitrator = FeedArbitrator::new(config);
+        let now = Instant::now();
+        
+        arbitrator.process(0, &header(1, 3), deletes(1, 3), now);
+        // The heartbeat says 4 and 5 were sent, but nothing is buffered behind them
+        assert!(arbitrator.process(0, &header(6, 0), Vec::new(), now).is_empty());
+        assert!(arbitrator.poll(now + timeout / 2).is_empty());
+        
+        assert_eq!(gaps(&arbitrator.poll(now + timeout)), vec![(4, 2)]);
+        assert_eq!(arbitrator.next_sequence(1), Some(6));
+        assert!(arbitrator.poll(now + timeout * 2).is_empty());
+    }
+}
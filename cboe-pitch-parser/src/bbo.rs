@@ -0,0 +1,136 @@
+use crate::{message::*, order_book::OrderBook};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Best bid and offer after a top-of-book change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BboUpdate {
+    pub symbol: String,
+    pub bid_price: Option<Price>,
+    pub bid_quantity: u64,
+    pub ask_price: Option<Price>,
+    pub ask_quantity: u64,
+    pub timestamp: Option<DateTime<Utc>>, // Exchange time of the change
+    pub sequence: Option<u32>,            // Last message of the frame that caused it
+}
+
+impl BboUpdate {
+    pub fn from_book(book: &OrderBook, sequence: Option<u32>) -> Self {
+        let bid = book.levels(Side::Buy).next();
+        let ask = book.levels(Side::Sell).next();
+        
+        Self {
+            symbol: book.symbol().to_string(),
+            bid_price: bid.map(|level| level.price()),
+            bid_quantity: bid.map_or(0, |level| level.quantity()),
+            ask_price: ask.map(|level| level.price()),
+            ask_quantity: ask.map_or(0, |level| level.quantity()),
+            timestamp: book.last_update(),
+            sequence,
+        }
+    }
+    
+    /// Same prices and quantities on both sides
+    pub fn same_top(&self, other: &BboUpdate) -> bool {
+        self.bid_price == other.bid_price
+            && self.bid_quantity == other.bid_quantity
+            && self.ask_price == other.ask_price
+            && self.ask_quantity == other.ask_quantity
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SubscriptionId(pub u64);
+
+type BboCallback = Box<dyn FnMut(&BboUpdate) + Send>;
+
+struct Subscription {
+    id: SubscriptionId,
+    symbols: Option<HashSet<String>>, // `None` watches every symbol
+    callback: BboCallback,
+}
+
+/// Top-of-book listeners and the last BBO published for each symbol
+#[derive(Default)]
+pub struct BboSubscriptions {
+    subscriptions: Vec<Subscription>,
+    last: HashMap<String, BboUpdate>,
+    next_id: u64,
+}
+
+impl std::fmt::Debug for BboSubscriptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BboSubscriptions")
+            .field("subscriptions", &self.subscriptions.len())
+            .field("symbols", &self.last.len())
+            .finish()
+    }
+}
+
+impl BboSubscriptions {
+    /// Register a callback for every symbol, or only `symbols` if given
+    pub fn subscribe<F>(&mut self, symbols: Option<&[&str]>, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&BboUpdate) + Send + 'static,
+    {
+        // Nothing was tracked while nobody listened, so start from a clean slate
+        if self.subscriptions.is_empty() {
+            self.last.clear();
+        }
+        
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        
+        self.subscriptions.push(Subscription {
+            id,
+            symbols: symbols.map(|symbols| symbols.iter().map(|symbol| symbol.to_string()).collect()),
+            callback: Box::new(callback),
+        });
+        id
+    }
+    
+    /// Returns false if the subscription did not exist
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let before = self.subscriptions.len();
+        self.subscriptions.retain(|subscription| subscription.id != id);
+        self.subscriptions.len() != before
+    }
+    
+    pub fn len(&self) -> usize {
+        self.subscriptions.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+    
+    /// Fire interested callbacks if the book's top changed since it was last published
+    pub fn publish(&mut self, book: &OrderBook, sequence: Option<u32>) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+        
+        let update = BboUpdate::from_book(book, sequence);
+        
+        // A book seen for the first time only counts as a change once it has a top
+        let changed = match self.last.get(book.symbol()) {
+            Some(last) => !last.same_top(&update),
+            None => update.bid_price.is_some() || update.ask_price.is_some(),
+        };
+        if !changed {
+            return;
+        }
+        
+        for subscription in &mut self.subscriptions {
+            let interested = subscription.symbols
+                .as_ref()
+                .is_none_or(|symbols| symbols.contains(&update.symbol));
+            if interested {
+                (subscription.callback)(&update);
+            }
+        }
+        
+        self.last.insert(update.symbol.clone(), update);
+    }
+}
@@ -0,0 +1,141 @@
+use cboe_pitch_parser::*;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+
+struct Args {
+    file: String,
+    filter: PacketFilter,
+    units: Vec<u8>,
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: pitch_analyzer --file <capture.pcap|pcapng> [--group <ip>]... [--port <port>]... [--unit <unit>]...");
+    std::process::exit(2);
+}
+
+fn parse_args() -> Args {
+    let mut file = None;
+    let mut filter = PacketFilter::default();
+    let mut units = Vec::new();
+    let mut args = std::env::args().skip(1);
+    
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--file" | "-f" => file = Some(value),
+            "--group" | "-g" => filter.groups.push(value.parse::<Ipv4Addr>().unwrap_or_else(|_| usage())),
+            "--port" | "-p" => filter.ports.push(value.parse().unwrap_or_else(|_| usage())),
+            "--unit" | "-u" => units.push(value.parse().unwrap_or_else(|_| usage())),
+            _ => usage(),
+        }
+    }
+    
+    Args {
+        file: file.unwrap_or_else(|| usage()),
+        filter,
+        units,
+    }
+}
+
+#[derive(Default)]
+struct UnitSummary {
+    frames: u64,
+    heartbeats: u64,
+    messages: u64,
+    first_sequence: Option<u32>,
+    next_sequence: Option<u32>,
+    gaps: u64,
+    gap_messages: u64,
+    duplicates: u64,
+}
+
+fn main() -> Result<()> {
+    let args = parse_args();
+    
+    println!("🔍 PITCH Capture Analyzer");
+    println!("=========================");
+    println!("   File: {}", args.file);
+    
+    let mut reader = PcapReader::open(&args.file)?.with_filter(args.filter);
+    println!("   Format: {:?}", reader.format());
+    
+    let mut units: BTreeMap<u8, UnitSummary> = BTreeMap::new();
+    let mut message_types: BTreeMap<u8, u64> = BTreeMap::new();
+    let mut malformed = 0u64;
+    let mut first_timestamp = None;
+    let mut last_timestamp = None;
+    
+    loop {
+        let (packet, header, messages) = match reader.next_parsed_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(PitchError::Io(e)) => return Err(PitchError::Io(e)),
+            Err(_) => {
+                malformed += 1;
+                continue;
+            }
+        };
+        
+        if !args.units.is_empty() && !args.units.contains(&header.unit) {
+            continue;
+        }
+        
+        first_timestamp.get_or_insert(packet.timestamp);
+        last_timestamp = Some(packet.timestamp);
+        
+        let summary = units.entry(header.unit).or_default();
+        summary.frames += 1;
+        summary.messages += messages.len() as u64;
+        
+        if header.is_heartbeat() {
+            summary.heartbeats += 1;
+        }
+        
+        summary.first_sequence.get_or_insert(header.sequence);
+        let end = header.sequence.wrapping_add(header.count as u32);
+        
+        match summary.next_sequence {
+            Some(expected) if header.sequence > expected => {
+                summary.gaps += 1;
+                summary.gap_messages += (header.sequence - expected) as u64;
+                summary.next_sequence = Some(end);
+            },
+            Some(expected) if end <= expected && !header.is_heartbeat() => summary.duplicates += 1,
+            _ => summary.next_sequence = Some(end.max(summary.next_sequence.unwrap_or(0))),
+        }
+        
+        for message in &messages {
+            *message_types.entry(message.message_type()).or_default() += 1;
+        }
+    }
+    
+    let stats = reader.stats();
+    
+    println!("\n📦 Capture:");
+    println!("   Records: {}", stats.records);
+    println!("   UDP packets: {} (filtered out: {})", stats.packets, stats.filtered);
+    println!("   Non-UDP records: {}", stats.non_udp);
+    println!("   Truncated: {}", stats.truncated);
+    println!("   IP fragments: {} (reassembled: {}, dropped: {})",
+            stats.fragments, stats.reassembled, stats.dropped_fragments);
+    println!("   Malformed frames: {}", malformed);
+    
+    if let (Some(first), Some(last)) = (first_timestamp, last_timestamp) {
+        println!("   Time range: {} -> {}", first, last);
+    }
+    
+    println!("\n📊 Units:");
+    for (unit, summary) in &units {
+        println!("   Unit {}: frames={}, heartbeats={}, messages={}, seq {}..{}, gaps={} ({} messages), duplicates={}",
+                unit, summary.frames, summary.heartbeats, summary.messages,
+                summary.first_sequence.unwrap_or(0), summary.next_sequence.unwrap_or(0),
+                summary.gaps, summary.gap_messages, summary.duplicates);
+    }
+    
+    println!("\n📨 Message types:");
+    for (message_type, count) in &message_types {
+        println!("   0x{:02X}: {}", message_type, count);
+    }
+    
+    Ok(())
+}
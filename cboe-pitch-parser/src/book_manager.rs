@@ -0,0 +1,701 @@
+use crate::{bbo::*, diagnostics::*, error::*, message::*, order_book::*};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// One book's level changes from a single frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookUpdate {
+    pub symbol: String,
+    pub sequence: Option<u32>, // Last message of the frame, when applied with `apply_frame`
+    pub timestamp: Option<DateTime<Utc>>,
+    pub levels: Vec<LevelUpdate>,
+}
+
+
+/// All order books for a feed, with order-ID routing
+///
+/// Order-keyed messages (executions, reduces, modifies, deletes) carry no symbol,
+/// so the manager remembers which book each resting order lives in. Books also
+/// remember the unit they were published on so Unit Clear purges only that unit.
+#[derive(Debug, Default)]
+pub struct BookManager {
+    books: Vec<OrderBook>,
+    symbols: HashMap<String, usize>, // Symbol -> index into `books`
+    orders: HashMap<OrderId, usize>, // Resting order -> index into `books`
+    units: Vec<Option<u8>>,          // Unit of each book, once seen in a frame
+    track_levels: bool,
+    changed: Vec<usize>,             // Books with pending level updates
+    sequence: Option<u32>,
+    bbo: BboSubscriptions,
+    bbo_pending: Vec<usize>,         // Books to check for a top-of-book change
+    watermarks: BTreeMap<u8, u32>,   // Last applied sequence per unit
+    validation_interval: Option<u64>,
+    unvalidated: BTreeSet<usize>,    // Books touched since the last automatic validation
+    messages_since_validation: u64,
+    diagnostics: Vec<BookDiagnostic>,
+}
+
+impl BookManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Apply a frame's messages with its unit known; returns the failures.
+    /// With level tracking on, follow with `take_level_updates` for the frame's batch.
+    pub fn apply_frame(&mut self, header: &SequencedUnitHeader, messages: &[PitchMessage]) -> Vec<PitchError> {
+        self.sequence = Some(header.sequence.wrapping_add(messages.len().saturating_sub(1) as u32));
+        if let (Some(sequence), false) = (self.sequence, messages.is_empty()) {
+            self.watermarks.insert(header.unit, sequence);
+        }
+        
+        let errors = messages
+            .iter()
+            .filter_map(|message| self.apply(Some(header.unit), message).err())
+            .collect();
+        
+        // BBO subscribers see the frame's net effect, never its intermediate states
+        self.publish_bbo();
+        errors
+    }
+    
+    /// Route a message to the one book it affects; returns that book's symbol,
+    /// or `None` for messages no single book is interested in.
+    ///
+    /// Without a unit, Unit Clear and End of Session apply to every book; use
+    /// `apply_frame` on multi-unit feeds.
+    pub fn apply_message(&mut self, message: &PitchMessage) -> Result<Option<&str>> {
+        self.sequence = None;
+        let result = self.apply(None, message);
+        self.publish_bbo();
+        
+        Ok(result?.map(|index| self.books[index].symbol()))
+    }
+    
+    fn apply(&mut self, unit: Option<u8>, message: &PitchMessage) -> Result<Option<usize>> {
+        let result = self.route(unit, message);
+        
+        if let Some(interval) = self.validation_interval {
+            self.messages_since_validation += 1;
+            if self.messages_since_validation >= interval {
+                self.validate_touched();
+            }
+        }
+        
+        result
+    }
+    
+    fn route(&mut self, unit: Option<u8>, message: &PitchMessage) -> Result<Option<usize>> {
+        let index = match message {
+            PitchMessage::UnitClear { .. } | PitchMessage::EndOfSession { .. } => {
+                self.apply_unit_message(unit, message)?;
+                return Ok(None);
+            },
+            PitchMessage::AddOrder { order_id, symbol, .. } => {
+                // Undisclosed orders are indexed too; their deletes and modifies arrive by ID
+                let index = self.book_index_or_insert(symbol, unit);
+                if let Some(previous) = self.orders.insert(*order_id, index) {
+                    if previous != index {
+                        self.evict_order(previous, *order_id);
+                    }
+                }
+                index
+            },
+            PitchMessage::TradingStatus { symbol, .. } => self.book_index_or_insert(symbol, unit),
+            PitchMessage::OrderExecuted { order_id, .. }
+            | PitchMessage::OrderExecutedAtPrice { order_id, .. }
+            | PitchMessage::ReduceSize { order_id, .. }
+            | PitchMessage::ModifyOrder { order_id, .. }
+            | PitchMessage::DeleteOrder { order_id, .. } => {
+                *self.orders.get(order_id).ok_or(PitchError::UnknownOrder(*order_id))?
+            },
+            PitchMessage::Trade { symbol, .. }
+            | PitchMessage::CalculatedValue { symbol, .. }
+            | PitchMessage::AuctionUpdate { symbol, .. }
+            | PitchMessage::AuctionSummary { symbol, .. } => match self.symbols.get(symbol) {
+                Some(&index) => index,
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        
+        let book = &mut self.books[index];
+        let was_unchanged = !book.has_level_updates();
+        let result = book.apply_message(message);
+        
+        if was_unchanged && book.has_level_updates() {
+            self.changed.push(index);
+        }
+        if !self.bbo.is_empty() && self.bbo_pending.last() != Some(&index) {
+            self.bbo_pending.push(index);
+        }
+        if self.validation_interval.is_some() {
+            self.unvalidated.insert(index);
+        }
+        
+        // Forget orders the book no longer holds so the index cannot grow without bound
+        if let Some(order_id) = order_id_of(message) {
+            if !book.contains_order(order_id) && !book.contains_hidden_order(order_id) {
+                self.orders.remove(&order_id);
+            }
+        }
+        
+        result.map(|_| Some(index))
+    }
+    
+    /// Drop an order ID that reappeared on another book so the old book keeps no ghost copy
+    fn evict_order(&mut self, index: usize, order_id: OrderId) {
+        let book = &mut self.books[index];
+        let was_unchanged = !book.has_level_updates();
+        book.remove_order(order_id);
+        book.remove_hidden_order(order_id);
+        
+        if was_unchanged && book.has_level_updates() {
+            self.changed.push(index);
+        }
+        if !self.bbo.is_empty() {
+            self.bbo_pending.push(index);
+        }
+        if self.validation_interval.is_some() {
+            self.unvalidated.insert(index);
+        }
+    }
+    
+    /// Apply every message even if some fail; returns the failures
+    pub fn apply_messages(&mut self, messages: &[PitchMessage]) -> Vec<PitchError> {
+        messages
+            .iter()
+            .filter_map(|message| self.apply_message(message).err())
+            .collect()
+    }
+    
+    fn apply_unit_message(&mut self, unit: Option<u8>, message: &PitchMessage) -> Result<()> {
+        for (index, (book, &book_unit)) in self.books.iter_mut().zip(&self.units).enumerate() {
+            if unit.is_none() || book_unit == unit {
+                let was_unchanged = !book.has_level_updates();
+                book.apply_message(message)?;
+                
+                if was_unchanged && book.has_level_updates() {
+                    self.changed.push(index);
+                }
+                if !self.bbo.is_empty() {
+                    self.bbo_pending.push(index);
+                }
+                if self.validation_interval.is_some() {
+                    self.unvalidated.insert(index);
+                }
+            }
+        }
+        
+        if let PitchMessage::UnitClear { .. } = message {
+            let books = &self.books;
+            self.orders.retain(|&order_id, &mut index| {
+                books[index].contains_order(order_id) || books[index].contains_hidden_order(order_id)
+            });
+        }
+        
+        Ok(())
+    }
+    
+    fn book_index_or_insert(&mut self, symbol: &str, unit: Option<u8>) -> usize {
+        let index = match self.symbols.get(symbol) {
+            Some(&index) => index,
+            None => {
+                let mut book = OrderBook::new(symbol.to_string());
+                book.set_level_tracking(self.track_levels);
+                self.books.push(book);
+                self.units.push(None);
+                self.symbols.insert(symbol.to_string(), self.books.len() - 1);
+                self.books.len() - 1
+            },
+        };
+        
+        if unit.is_some() {
+            self.units[index] = unit;
+        }
+        index
+    }
+    
+    /// Check every book's invariants now
+    pub fn validate(&self) -> Vec<BookDiagnostic> {
+        self.books
+            .iter()
+            .flat_map(|book| {
+                book.diagnose().into_iter().map(|issue| BookDiagnostic {
+                    symbol: book.symbol().to_string(),
+                    issue,
+                })
+            })
+            .collect()
+    }
+    
+    /// Validate the books touched in the last `messages` messages, every `messages`
+    /// messages; `None` turns it off. Findings collect until `take_diagnostics`.
+    pub fn set_validation_interval(&mut self, messages: Option<u64>) {
+        self.validation_interval = messages.map(|messages| messages.max(1));
+        self.messages_since_validation = 0;
+        self.unvalidated.clear();
+    }
+    
+    pub fn take_diagnostics(&mut self) -> Vec<BookDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+    
+    fn validate_touched(&mut self) {
+        for index in std::mem::take(&mut self.unvalidated) {
+            let book = &self.books[index];
+            self.diagnostics.extend(book.diagnose().into_iter().map(|issue| BookDiagnostic {
+                symbol: book.symbol().to_string(),
+                issue,
+            }));
+        }
+        self.messages_since_validation = 0;
+    }
+    
+    /// Sequence of the last message applied from `unit` via `apply_frame`
+    pub fn last_sequence(&self, unit: u8) -> Option<u32> {
+        self.watermarks.get(&unit).copied()
+    }
+    
+    /// `(unit, last applied sequence)` for every unit seen, in unit order
+    pub fn watermarks(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.watermarks.iter().map(|(&unit, &sequence)| (unit, sequence))
+    }
+    
+    pub(crate) fn set_last_sequence(&mut self, unit: u8, sequence: u32) {
+        self.watermarks.insert(unit, sequence);
+    }
+    
+    /// Point an order at `symbol`'s book after it was added outside `apply_message`
+    pub(crate) fn index_order(&mut self, order_id: OrderId, symbol: &str) {
+        if let Some(&index) = self.symbols.get(symbol) {
+            self.orders.insert(order_id, index);
+        }
+    }
+    
+    pub(crate) fn forget_order(&mut self, order_id: OrderId) {
+        self.orders.remove(&order_id);
+    }
+    
+    /// Add a fully built book, e.g. one read back from a checkpoint
+    pub(crate) fn insert_book(&mut self, mut book: OrderBook, unit: Option<u8>) {
+        let index = self.book_index_or_insert(book.symbol(), unit);
+        book.set_level_tracking(self.track_levels);
+        
+        for order_id in book.orders().map(|order| order.order_id).chain(book.hidden_orders().map(|order| order.order_id)) {
+            self.orders.insert(order_id, index);
+        }
+        self.books[index] = book;
+    }
+    
+    /// Start or stop recording level changes in every book, current and future
+    pub fn set_level_tracking(&mut self, enabled: bool) {
+        self.track_levels = enabled;
+        for book in &mut self.books {
+            book.set_level_tracking(enabled);
+        }
+        if !enabled {
+            self.changed.clear();
+        }
+    }
+    
+    /// Level changes since the last call, one batch per changed book
+    pub fn take_level_updates(&mut self) -> Vec<BookUpdate> {
+        let mut updates = Vec::with_capacity(self.changed.len());
+        
+        for index in std::mem::take(&mut self.changed) {
+            let book = &mut self.books[index];
+            let levels = book.take_level_updates();
+            
+            // Changes that cancelled out within the batch produce nothing
+            if !levels.is_empty() {
+                updates.push(BookUpdate {
+                    symbol: book.symbol().to_string(),
+                    sequence: self.sequence,
+                    timestamp: book.last_update(),
+                    levels,
+                });
+            }
+        }
+        
+        updates
+    }
+    
+    /// Call `callback` whenever the best bid or offer changes, for every symbol
+    /// or only `symbols` if given. Changes are reported once per frame.
+    pub fn subscribe_bbo<F>(&mut self, symbols: Option<&[&str]>, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&BboUpdate) + Send + 'static,
+    {
+        self.bbo.subscribe(symbols, callback)
+    }
+    
+    pub fn unsubscribe_bbo(&mut self, id: SubscriptionId) -> bool {
+        self.bbo.unsubscribe(id)
+    }
+    
+    fn publish_bbo(&mut self) {
+        for index in std::mem::take(&mut self.bbo_pending) {
+            self.bbo.publish(&self.books[index], self.sequence);
+        }
+    }
+    
+    /// Flag every book on a unit, e.g. `Recovering` after a sequence gap
+    pub fn set_unit_state(&mut self, unit: u8, state: BookState) {
+        for (book, &book_unit) in self.books.iter_mut().zip(&self.units) {
+            if book_unit == Some(unit) {
+                book.set_state(state);
+            }
+        }
+    }
+    
+    pub fn unit_of(&self, symbol: &str) -> Option<u8> {
+        self.symbols.get(symbol).and_then(|&index| self.units[index])
+    }
+    
+    pub fn books_on_unit(&self, unit: u8) -> impl Iterator<Item = &OrderBook> {
+        self.books
+            .iter()
+            .zip(&self.units)
+            .filter(move |(_, &book_unit)| book_unit == Some(unit))
+            .map(|(book, _)| book)
+    }
+    
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.symbols.get(symbol).map(|&index| &self.books[index])
+    }
+    
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut OrderBook> {
+        self.symbols.get(symbol).map(|&index| &mut self.books[index])
+    }
+    
+    /// Book holding a resting order
+    pub fn book_for_order(&self, order_id: OrderId) -> Option<&OrderBook> {
+        self.orders.get(&order_id).map(|&index| &self.books[index])
+    }
+    
+    pub fn symbol_for_order(&self, order_id: OrderId) -> Option<&str> {
+        self.book_for_order(order_id).map(|book| book.symbol())
+    }
+    
+    /// Books in the order their symbols were first seen
+    pub fn books(&self) -> impl Iterator<Item = &OrderBook> {
+        self.books.iter()
+    }
+    
+    pub fn books_mut(&mut self) -> impl Iterator<Item = &mut OrderBook> {
+        self.books.iter_mut()
+    }
+    
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.books.iter().map(|book| book.symbol())
+    }
+    
+    pub fn book_count(&self) -> usize {
+        self.books.len()
+    }
+    
+    /// Resting orders across all books, undisclosed ones included
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+}
+
+fn order_id_of(message: &PitchMessage) -> Option<OrderId> {
+    match message {
+        PitchMessage::AddOrder { order_id, .. }
+        | PitchMessage::OrderExecuted { order_id, .. }
+        | PitchMessage::OrderExecutedAtPrice { order_id, .. }
+        | PitchMessage::ReduceSize { order_id, .. }
+        | PitchMessage::ModifyOrder { order_id, .. }
+        | PitchMessage::DeleteOrder { order_id, .. } => Some(*order_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::{Arc, Mutex};
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn header(unit: u8, sequence: u32) -> SequencedUnitHeader {
+        SequencedUnitHeader { length: 0, count: 1, unit, sequence }
+    }
+    
+    fn add_order(order_id: u64, symbol: &str, side: Side, quantity: u32, price: u64) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: at(order_id as i64),
+            order_id: OrderId(order_id),
+            side,
+            quantity,
+            symbol: symbol.to_string(),
+            price: Price(price),
+            pid: "TEST".to_string(),
+        }
+    }
+    
+    fn add(order_id: u64, symbol: &str, quantity: u32, price: u64) -> PitchMessage {
+        add_order(order_id, symbol, Side::Buy, quantity, price)
+    }
+    
+    fn offer(order_id: u64, symbol: &str, quantity: u32, price: u64) -> PitchMessage {
+        add_order(order_id, symbol, Side::Sell, quantity, price)
+    }
+    
+    fn execute(order_id: u64, quantity: u32) -> PitchMessage {
+        PitchMessage::OrderExecuted {
+            timestamp: at(50),
+            order_id: OrderId(order_id),
+            executed_quantity: quantity,
+            execution_id: ExecutionId(1),
+            contra_order_id: OrderId(0),
+            contra_pid: String::new(),
+        }
+    }
+    
+    fn delete(order_id: u64) -> PitchMessage {
+        PitchMessage::DeleteOrder {
+            timestamp: at(50),
+            order_id: OrderId(order_id),
+        }
+    }
+    
+    #[test]
+    fn order_keyed_messages_route_to_the_adding_book() {
+        let mut manager = BookManager::new();
+        manager.apply_messages(&[add(1, "AAPL", 100, 1_000), add(2, "MSFT", 200, 2_000)]);
+        
+        assert_eq!(manager.apply_message(&execute(2, 50)).unwrap(), Some("MSFT"));
+        assert_eq!(manager.book("MSFT").unwrap().order(OrderId(2)).unwrap().quantity, 150);
+        assert_eq!(manager.book("AAPL").unwrap().order(OrderId(1)).unwrap().quantity, 100);
+        
+        assert_eq!(manager.apply_message(&delete(1)).unwrap(), Some("AAPL"));
+        assert_eq!(manager.symbol_for_order(OrderId(1)), None);
+        assert_eq!(manager.symbol_for_order(OrderId(2)), Some("MSFT"));
+        assert_eq!(manager.order_count(), 1);
+    }
+    
+    #[test]
+    fn unknown_orders_are_errors() {
+        let mut manager = BookManager::new();
+        manager.apply_message(&add(1, "AAPL", 100, 1_000)).unwrap();
+        
+        assert!(matches!(manager.apply_message(&delete(9)), Err(PitchError::UnknownOrder(OrderId(9)))));
+        assert!(matches!(manager.apply_message(&execute(9, 10)), Err(PitchError::UnknownOrder(OrderId(9)))));
+        
+        // A deleted order is forgotten, so a second delete fails too
+        manager.apply_message(&delete(1)).unwrap();
+        assert!(matches!(manager.apply_message(&delete(1)), Err(PitchError::UnknownOrder(OrderId(1)))));
+    }
+    
+    #[test]
+    fn reused_order_id_leaves_no_ghost_in_the_old_book() {
+        let mut manager = BookManager::new();
+        manager.apply_message(&add(1, "AAPL", 100, 1_000)).unwrap();
+        manager.apply_message(&add(1, "MSFT", 200, 2_000)).unwrap();
+        
+        assert!(!manager.book("AAPL").unwrap().contains_order(OrderId(1)));
+        assert_eq!(manager.book("AAPL").unwrap().best_bid(), None);
+        assert_eq!(manager.symbol_for_order(OrderId(1)), Some("MSFT"));
+        assert_eq!(manager.order_count(), 1);
+        
+        manager.apply_message(&delete(1)).unwrap();
+        assert_eq!(manager.book("MSFT").unwrap().order_count(), 0);
+    }
+    
+    #[test]
+    fn unit_clear_drops_only_that_units_orders() {
+        let mut manager = BookManager::new();
+        manager.apply_frame(&header(1, 1), &[add(1, "AAPL", 100, 1_000)]);
+        manager.apply_frame(&header(1, 2), &[add(2, "AAPL", 0, 1_000)]); // Undisclosed
+        manager.apply_frame(&header(2, 1), &[add(3, "MSFT", 200, 2_000)]);
+        
+        let errors = manager.apply_frame(&header(1, 3), &[PitchMessage::UnitClear { timestamp: at(60) }]);
+        
+        assert!(errors.is_empty());
+        assert_eq!(manager.book("AAPL").unwrap().order_count(), 0);
+        assert_eq!(manager.book("AAPL").unwrap().hidden_order_count(), 0);
+        assert_eq!(manager.book("MSFT").unwrap().order_count(), 1);
+        assert_eq!(manager.order_count(), 1);
+        assert_eq!(manager.symbol_for_order(OrderId(3)), Some("MSFT"));
+        assert!(matches!(manager.apply_message(&delete(1)), Err(PitchError::UnknownOrder(_))));
+        assert!(matches!(manager.apply_message(&delete(2)), Err(PitchError::UnknownOrder(_))));
+        assert_eq!(manager.last_sequence(1), Some(3));
+        assert_eq!(manager.last_sequence(2), Some(1));
+    }
+    
+    #[test]
+    fn unit_clear_and_end_of_session_follow_the_book_lifecycle() {
+        let mut manager = BookManager::new();
+        manager.apply_frame(&header(1, 1), &[add(1, "AAPL", 100, 1_000)]);
+        manager.apply_frame(&header(2, 1), &[add(2, "MSFT", 200, 2_000)]);
+        
+        manager.apply_frame(&header(1, 2), &[PitchMessage::UnitClear { timestamp: at(60) }]);
+        assert_eq!(manager.book("AAPL").unwrap().state(), BookState::Cleared);
+        assert_eq!(manager.book("MSFT").unwrap().state(), BookState::Live);
+        
+        // The unit republishing its orders brings the book back
+        manager.apply_frame(&header(1, 3), &[add(3, "AAPL", 100, 1_000)]);
+        assert_eq!(manager.book("AAPL").unwrap().state(), BookState::Live);
+        
+        manager.apply_frame(&header(2, 2), &[PitchMessage::EndOfSession { timestamp: at(70) }]);
+        assert_eq!(manager.book("MSFT").unwrap().state(), BookState::Final);
+        assert_eq!(manager.book("AAPL").unwrap().state(), BookState::Live);
+        assert_eq!(manager.book("MSFT").unwrap().order_count(), 1);
+        
+        // Without a unit, End of Session reaches every book
+        manager.apply_message(&PitchMessage::EndOfSession { timestamp: at(80) }).unwrap();
+        assert!(manager.books().all(|book| book.state() == BookState::Final));
+    }
+    
+    #[test]
+    fn recovering_unit_stays_unreliable_until_cleared() {
+        let mut manager = BookManager::new();
+        manager.apply_frame(&header(1, 1), &[add(1, "AAPL", 100, 1_000)]);
+        manager.apply_frame(&header(2, 1), &[add(2, "MSFT", 200, 2_000)]);
+        
+        manager.set_unit_state(1, BookState::Recovering);
+        manager.apply_frame(&header(1, 5), &[add(3, "AAPL", 100, 1_000)]);
+        assert_eq!(manager.book("AAPL").unwrap().state(), BookState::Recovering);
+        assert_eq!(manager.book("MSFT").unwrap().state(), BookState::Live);
+        
+        manager.apply_frame(&header(1, 6), &[PitchMessage::UnitClear { timestamp: at(60) }]);
+        manager.apply_frame(&header(1, 7), &[add(4, "AAPL", 100, 1_000)]);
+        assert_eq!(manager.book("AAPL").unwrap().state(), BookState::Live);
+        assert_eq!(manager.book("AAPL").unwrap().order_count(), 1);
+    }
+    
+    fn level(price: u64, quantity: u64, order_count: usize, level_index: usize, action: LevelAction) -> LevelUpdate {
+        LevelUpdate {
+            side: Side::Buy,
+            price: Price(price),
+            quantity,
+            order_count,
+            level_index,
+            action,
+        }
+    }
+    
+    #[test]
+    fn level_updates_are_coalesced_per_frame() {
+        let mut manager = BookManager::new();
+        manager.set_level_tracking(true);
+        
+        manager.apply_frame(&header(1, 1), &[
+            add(1, "AAPL", 100, 1_000),
+            add(2, "AAPL", 200, 1_000),
+            add(3, "AAPL", 300, 990),
+            add(4, "AAPL", 400, 980),
+            delete(4),
+        ]);
+        let updates = manager.take_level_updates();
+        
+        // The 980 level came and went inside the frame, so it is not reported
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].symbol, "AAPL");
+        assert_eq!(updates[0].sequence, Some(5));
+        assert_eq!(updates[0].levels, vec![
+            level(1_000, 300, 2, 0, LevelAction::Insert),
+            level(990, 300, 1, 1, LevelAction::Insert),
+        ]);
+        assert!(manager.take_level_updates().is_empty());
+        
+        // Emptied and refilled within a frame nets out to one update
+        manager.apply_frame(&header(1, 6), &[delete(1), delete(2), add(5, "AAPL", 50, 1_000)]);
+        assert_eq!(manager.take_level_updates()[0].levels, vec![level(1_000, 50, 1, 0, LevelAction::Update)]);
+    }
+    
+    #[test]
+    fn deleted_level_reports_where_it_was() {
+        let mut manager = BookManager::new();
+        manager.set_level_tracking(true);
+        manager.apply_frame(&header(1, 1), &[
+            add(1, "AAPL", 100, 1_010),
+            add(2, "AAPL", 200, 1_000),
+            add(3, "AAPL", 300, 990),
+        ]);
+        manager.take_level_updates();
+        
+        manager.apply_frame(&header(1, 4), &[delete(2)]);
+        
+        assert_eq!(manager.take_level_updates()[0].levels, vec![level(1_000, 0, 0, 1, LevelAction::Delete)]);
+    }
+    
+    #[test]
+    fn level_updates_are_batched_per_book() {
+        let mut manager = BookManager::new();
+        manager.set_level_tracking(true);
+        
+        manager.apply_frame(&header(1, 1), &[add(1, "AAPL", 100, 1_000), add(2, "MSFT", 200, 2_000), execute(1, 40)]);
+        let updates = manager.take_level_updates();
+        
+        let symbols: Vec<&str> = updates.iter().map(|update| update.symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["AAPL", "MSFT"]);
+        assert_eq!(updates[0].levels, vec![level(1_000, 60, 1, 0, LevelAction::Insert)]);
+    }
+    
+    fn record_bbo(manager: &mut BookManager, symbols: Option<&[&str]>) -> (SubscriptionId, Arc<Mutex<Vec<BboUpdate>>>) {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = updates.clone();
+        let id = manager.subscribe_bbo(symbols, move |update| sink.lock().unwrap().push(update.clone()));
+        (id, updates)
+    }
+    
+    #[test]
+    fn bbo_is_published_once_per_frame() {
+        let mut manager = BookManager::new();
+        let (_, updates) = record_bbo(&mut manager, None);
+        
+        manager.apply_frame(&header(1, 1), &[
+            add(1, "AAPL", 100, 1_000),
+            add(2, "AAPL", 200, 1_010),
+            offer(3, "AAPL", 300, 1_030),
+            offer(4, "AAPL", 400, 1_020),
+        ]);
+        
+        {
+            let updates = updates.lock().unwrap();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0].bid_price, Some(Price(1_010)));
+            assert_eq!(updates[0].bid_quantity, 200);
+            assert_eq!(updates[0].ask_price, Some(Price(1_020)));
+            assert_eq!(updates[0].ask_quantity, 400);
+            assert_eq!(updates[0].sequence, Some(4));
+        }
+        
+        // Changes behind the top, or that revert within the frame, publish nothing
+        manager.apply_frame(&header(1, 5), &[add(5, "AAPL", 100, 990)]);
+        manager.apply_frame(&header(1, 6), &[add(6, "AAPL", 100, 1_015), delete(6)]);
+        assert_eq!(updates.lock().unwrap().len(), 1);
+        
+        manager.apply_frame(&header(1, 8), &[execute(2, 50)]);
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].bid_quantity, 150);
+    }
+    
+    #[test]
+    fn bbo_subscriptions_filter_by_symbol_and_unsubscribe() {
+        let mut manager = BookManager::new();
+        let (id, msft) = record_bbo(&mut manager, Some(&["MSFT"]));
+        let (_, all) = record_bbo(&mut manager, None);
+        
+        manager.apply_frame(&header(1, 1), &[add(1, "AAPL", 100, 1_000), add(2, "MSFT", 200, 2_000)]);
+        
+        let symbols: Vec<String> = msft.lock().unwrap().iter().map(|update| update.symbol.clone()).collect();
+        assert_eq!(symbols, vec!["MSFT"]);
+        assert_eq!(all.lock().unwrap().len(), 2);
+        
+        assert!(manager.unsubscribe_bbo(id));
+        assert!(!manager.unsubscribe_bbo(id));
+        manager.apply_frame(&header(1, 3), &[add(3, "MSFT", 200, 2_010)]);
+        
+        assert_eq!(msft.lock().unwrap().len(), 1);
+        assert_eq!(all.lock().unwrap().len(), 3);
+    }
+}
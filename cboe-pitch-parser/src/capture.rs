@@ -0,0 +1,491 @@
+use crate::{error::*, message::*, parser::PitchParser, receiver::Feed};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+const FILE_MAGIC: &[u8; 8] = b"PITCHCAP";
+const FOOTER_MAGIC: &[u8; 8] = b"PITCHIDX";
+const FORMAT_VERSION: u16 = 1;
+
+const FILE_HEADER_LEN: u64 = 40;
+const FOOTER_LEN: u64 = 24;
+const INDEX_ENTRY_LEN: usize = 28;
+
+/// Identifies a recorded session
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureHeader {
+    pub unit: u8,
+    pub feed: Feed,
+    pub date: NaiveDate,
+    pub session_id: Uuid,
+}
+
+impl CaptureHeader {
+    pub fn new(unit: u8, feed: Feed, date: NaiveDate) -> Self {
+        Self {
+            unit,
+            feed,
+            date,
+            session_id: Uuid::new_v4(),
+        }
+    }
+    
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(FILE_MAGIC)?;
+        writer.write_u16::<LittleEndian>(FORMAT_VERSION)?;
+        writer.write_u8(self.unit)?;
+        writer.write_u8(match self.feed {
+            Feed::A => b'A',
+            Feed::B => b'B',
+            Feed::E => b'E',
+        })?;
+        writer.write_i32::<LittleEndian>(self.date.num_days_from_ce())?;
+        writer.write_all(self.session_id.as_bytes())?;
+        writer.write_all(&[0u8; 8])?; // Reserved
+        Ok(())
+    }
+    
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != FILE_MAGIC {
+            return Err(PitchError::Parse("Not a PITCH capture file".to_string()));
+        }
+        
+        let version = reader.read_u16::<LittleEndian>()?;
+        if version != FORMAT_VERSION {
+            return Err(PitchError::Parse(format!("Unsupported capture version {}", version)));
+        }
+        
+        let unit = reader.read_u8()?;
+        let feed = match reader.read_u8()? {
+            b'A' => Feed::A,
+            b'B' => Feed::B,
+            b'E' => Feed::E,
+            other => return Err(PitchError::Parse(format!("Invalid feed: {}", other))),
+        };
+        
+        let days = reader.read_i32::<LittleEndian>()?;
+        let date = NaiveDate::from_num_days_from_ce_opt(days)
+            .ok_or_else(|| PitchError::Parse(format!("Invalid capture date: {}", days)))?;
+        
+        let mut uuid = [0u8; 16];
+        reader.read_exact(&mut uuid)?;
+        
+        let mut reserved = [0u8; 8];
+        reader.read_exact(&mut reserved)?;
+        
+        Ok(Self {
+            unit,
+            feed,
+            date,
+            session_id: Uuid::from_bytes(uuid),
+        })
+    }
+}
+
+/// Trailing index entry pointing at a frame record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureIndexEntry {
+    pub sequence: u32,      // Sequence of the first message in the frame
+    pub exchange_time: u64, // Latest exchange timestamp seen so far, nanoseconds
+    pub receive_time: u64,  // Nanoseconds
+    pub offset: u64,        // Byte offset of the frame record
+}
+
+/// A frame read back from a capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub received_at: DateTime<Utc>,
+    pub header: SequencedUnitHeader,
+    pub data: Vec<u8>,
+}
+
+impl RecordedFrame {
+    pub fn parse(&self, parser: &PitchParser) -> Result<Vec<PitchMessage>> {
+        parser.parse_frame(&self.data).map(|(_, messages)| messages)
+    }
+    
+    /// Exchange time of the first timestamped message, if any
+    pub fn exchange_time(&self) -> Option<DateTime<Utc>> {
+        PitchParser::first_timestamp_nanos(&self.data).map(nanos_to_datetime)
+    }
+}
+
+/// Appends frames with receive timestamps and writes a seek index on `finish`
+///
+/// Records are `[receive time u64][frame]`; the frame's own header gives its length.
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    frames: u64,
+    index_interval: u64,
+    index: Vec<CaptureIndexEntry>,
+    exchange_time: u64,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, header: &CaptureHeader) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(mut writer: W, header: &CaptureHeader) -> Result<Self> {
+        header.write_to(&mut writer)?;
+        
+        Ok(Self {
+            writer,
+            offset: FILE_HEADER_LEN,
+            frames: 0,
+            index_interval: 64,
+            index: Vec::new(),
+            exchange_time: 0,
+        })
+    }
+    
+    /// Index every `interval` frames; seeks scan at most this many frames
+    pub fn with_index_interval(mut self, interval: u64) -> Self {
+        self.index_interval = interval.max(1);
+        self
+    }
+    
+    pub fn write_frame(&mut self, received_at: DateTime<Utc>, frame: &[u8]) -> Result<()> {
+        let header = PitchParser::read_header(frame)?;
+        let length = header.length as usize;
+        if length < 8 || frame.len() < length {
+            return Err(PitchError::InsufficientData {
+                expected: length.max(8),
+                actual: frame.len(),
+            });
+        }
+        
+        if let Some(nanos) = PitchParser::first_timestamp_nanos(frame) {
+            self.exchange_time = self.exchange_time.max(nanos);
+        }
+        
+        let receive_time = datetime_to_nanos(received_at);
+        
+        if self.frames.is_multiple_of(self.index_interval) {
+            self.index.push(CaptureIndexEntry {
+                sequence: header.sequence,
+                exchange_time: self.exchange_time,
+                receive_time,
+                offset: self.offset,
+            });
+        }
+        
+        self.writer.write_u64::<LittleEndian>(receive_time)?;
+        self.writer.write_all(&frame[..length])?;
+        
+        self.offset += 8 + length as u64;
+        self.frames += 1;
+        Ok(())
+    }
+    
+    pub fn frames_written(&self) -> u64 {
+        self.frames
+    }
+    
+    /// Write the index and footer; the file is only seekable after this
+    pub fn finish(mut self) -> Result<W> {
+        let index_offset = self.offset;
+        
+        for entry in &self.index {
+            self.writer.write_u32::<LittleEndian>(entry.sequence)?;
+            self.writer.write_u64::<LittleEndian>(entry.exchange_time)?;
+            self.writer.write_u64::<LittleEndian>(entry.receive_time)?;
+            self.writer.write_u64::<LittleEndian>(entry.offset)?;
+        }
+        
+        self.writer.write_u64::<LittleEndian>(index_offset)?;
+        self.writer.write_u64::<LittleEndian>(self.index.len() as u64)?;
+        self.writer.write_all(FOOTER_MAGIC)?;
+        self.writer.flush()?;
+        
+        Ok(self.writer)
+    }
+}
+
+/// Reads captures sequentially or from an indexed time/sequence position
+pub struct CaptureReader<R: Read + Seek> {
+    reader: R,
+    header: CaptureHeader,
+    index: Vec<CaptureIndexEntry>,
+    data_end: u64, // Start of the index, or end of file for unfinished captures
+    position: u64,
+    truncated: u64,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        reader.seek(SeekFrom::Start(0))?;
+        let header = CaptureHeader::read_from(&mut reader)?;
+        
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        let (index, data_end) = Self::read_index(&mut reader, file_len)?
+            .unwrap_or((Vec::new(), file_len));
+        
+        reader.seek(SeekFrom::Start(FILE_HEADER_LEN))?;
+        
+        Ok(Self {
+            reader,
+            header,
+            index,
+            data_end,
+            position: FILE_HEADER_LEN,
+            truncated: 0,
+        })
+    }
+    
+    fn read_index(reader: &mut R, file_len: u64) -> Result<Option<(Vec<CaptureIndexEntry>, u64)>> {
+        if file_len < FILE_HEADER_LEN + FOOTER_LEN {
+            return Ok(None);
+        }
+        
+        reader.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+        let index_offset = reader.read_u64::<LittleEndian>()?;
+        let count = reader.read_u64::<LittleEndian>()?;
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        
+        // Without a footer the writer never finished; fall back to scanning
+        let index_len = count.checked_mul(INDEX_ENTRY_LEN as u64);
+        if &magic != FOOTER_MAGIC || index_len.and_then(|len| index_offset.checked_add(len)) != Some(file_len - FOOTER_LEN) {
+            return Ok(None);
+        }
+        
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut index = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            index.push(CaptureIndexEntry {
+                sequence: reader.read_u32::<LittleEndian>()?,
+                exchange_time: reader.read_u64::<LittleEndian>()?,
+                receive_time: reader.read_u64::<LittleEndian>()?,
+                offset: reader.read_u64::<LittleEndian>()?,
+            });
+        }
+        
+        Ok(Some((index, index_offset)))
+    }
+    
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+    
+    pub fn index(&self) -> &[CaptureIndexEntry] {
+        &self.index
+    }
+    
+    pub fn is_indexed(&self) -> bool {
+        !self.index.is_empty()
+    }
+    
+    /// Records cut off at the end of the data, as left by a writer that never finished
+    pub fn truncated(&self) -> u64 {
+        self.truncated
+    }
+    
+    pub fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        if self.position >= self.data_end {
+            return Ok(None);
+        }
+        if self.position + 16 > self.data_end {
+            return self.truncate();
+        }
+        
+        let receive_time = self.reader.read_u64::<LittleEndian>()?;
+        let mut data = vec![0u8; 8];
+        self.reader.read_exact(&mut data)?;
+        let header = PitchParser::read_header(&data)?;
+        
+        let length = header.length as usize;
+        if length < 8 {
+            return Err(PitchError::Parse(format!("Corrupt frame record at offset {}", self.position)));
+        }
+        if self.position + 8 + length as u64 > self.data_end {
+            return self.truncate();
+        }
+        
+        data.resize(length, 0);
+        match self.reader.read_exact(&mut data[8..]) {
+            Ok(()) => {},
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return self.truncate(),
+            Err(e) => return Err(e.into()),
+        }
+        
+        self.position += 8 + length as u64;
+        
+        Ok(Some(RecordedFrame {
+            received_at: nanos_to_datetime(receive_time),
+            header,
+            data,
+        }))
+    }
+    
+    pub fn rewind(&mut self) -> Result<()> {
+        self.seek_offset(FILE_HEADER_LEN)
+    }
+    
+    /// Position at the first frame containing `sequence`, or the first after it
+    pub fn seek_to_sequence(&mut self, sequence: u32) -> Result<()> {
+        let start = self.index.partition_point(|entry| entry.sequence <= sequence);
+        let offset = start.checked_sub(1).map_or(FILE_HEADER_LEN, |i| self.index[i].offset);
+        
+        self.scan_from(offset, |frame| {
+            frame.header.sequence.wrapping_add((frame.header.count as u32).max(1)) > sequence
+        })
+    }
+    
+    /// Position at the first frame whose exchange time is at or after `time`
+    pub fn seek_to_time(&mut self, time: DateTime<Utc>) -> Result<()> {
+        let target = datetime_to_nanos(time);
+        let start = self.index.partition_point(|entry| entry.exchange_time < target);
+        let offset = start.checked_sub(1).map_or(FILE_HEADER_LEN, |i| self.index[i].offset);
+        
+        self.scan_from(offset, |frame| {
+            PitchParser::first_timestamp_nanos(&frame.data).is_some_and(|nanos| nanos >= target)
+        })
+    }
+    
+    fn scan_from<F: Fn(&RecordedFrame) -> bool>(&mut self, offset: u64, found: F) -> Result<()> {
+        self.seek_offset(offset)?;
+        
+        loop {
+            let frame_offset = self.position;
+            match self.next_frame()? {
+                Some(frame) if found(&frame) => return self.seek_offset(frame_offset),
+                Some(_) => continue,
+                None => return Ok(()),
+            }
+        }
+    }
+    
+    fn seek_offset(&mut self, offset: u64) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.position = offset;
+        Ok(())
+    }
+    
+    /// Treat a partial last record as the end of the data
+    fn truncate(&mut self) -> Result<Option<RecordedFrame>> {
+        self.truncated += 1;
+        self.position = self.data_end;
+        Ok(None)
+    }
+}
+
+impl<R: Read + Seek> Iterator for CaptureReader<R> {
+    type Item = Result<RecordedFrame>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+pub(crate) fn datetime_to_nanos(time: DateTime<Utc>) -> u64 {
+    time.timestamp_nanos_opt().unwrap_or(0).max(0) as u64
+}
+
+pub(crate) fn nanos_to_datetime(nanos: u64) -> DateTime<Utc> {
+    DateTime::from_timestamp((nanos / 1_000_000_000) as i64, (nanos % 1_000_000_000) as u32).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::PitchSimulator;
+    use chrono::TimeZone;
+    use std::io::Cursor;
+    
+    const BASE_NANOS: i64 = 1_700_000_000_000_000_000;
+    
+    /// Twenty two-message frames, sequences 1, 3, 5, ... one second apart
+    fn capture(finish: bool) -> Vec<u8> {
+        let header = CaptureHeader::new(1, Feed::A, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        let mut writer = CaptureWriter::new(Cursor::new(Vec::new()), &header).unwrap().with_index_interval(4);
+        let simulator = PitchSimulator::new();
+        
+        for i in 0..20u32 {
+            let timestamp = Utc.timestamp_nanos(BASE_NANOS + i as i64 * 1_000_000_000);
+            let messages: Vec<PitchMessage> = (0..2)
+                .map(|j| PitchMessage::DeleteOrder {
+                    timestamp,
+                    order_id: OrderId((i * 2 + j) as u64),
+                })
+                .collect();
+            let header = SequencedUnitHeader { length: 0, count: 2, unit: 1, sequence: 1 + i * 2 };
+            writer.write_frame(timestamp, &simulator.serialize_frame(&header, &messages).unwrap()).unwrap();
+        }
+        
+        if finish {
+            writer.finish().unwrap().into_inner()
+        } else {
+            writer.writer.into_inner()
+        }
+    }
+    
+    #[test]
+    fn index_is_written_every_interval() {
+        let reader = CaptureReader::new(Cursor::new(capture(true))).unwrap();
+        
+        assert!(reader.is_indexed());
+        let sequences: Vec<u32> = reader.index().iter().map(|entry| entry.sequence).collect();
+        assert_eq!(sequences, vec![1, 9, 17, 25, 33]);
+    }
+    
+    #[test]
+    fn seek_to_sequence_lands_on_the_containing_frame() {
+        let mut reader = CaptureReader::new(Cursor::new(capture(true))).unwrap();
+        
+        for (sequence, expected) in [(1, 1), (2, 1), (14, 13), (15, 15), (39, 39), (40, 39)] {
+            reader.seek_to_sequence(sequence).unwrap();
+            assert_eq!(reader.next_frame().unwrap().unwrap().header.sequence, expected, "sequence {}", sequence);
+        }
+        
+        reader.seek_to_sequence(41).unwrap();
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+    
+    #[test]
+    fn seek_to_time_lands_on_first_frame_at_or_after() {
+        let mut reader = CaptureReader::new(Cursor::new(capture(true))).unwrap();
+        
+        reader.seek_to_time(Utc.timestamp_nanos(BASE_NANOS + 6_500_000_000)).unwrap();
+        assert_eq!(reader.next_frame().unwrap().unwrap().header.sequence, 15);
+        
+        reader.seek_to_time(Utc.timestamp_nanos(BASE_NANOS)).unwrap();
+        assert_eq!(reader.next_frame().unwrap().unwrap().header.sequence, 1);
+    }
+    
+    #[test]
+    fn unfinished_capture_is_scanned_without_index() {
+        let mut reader = CaptureReader::new(Cursor::new(capture(false))).unwrap();
+        
+        assert!(!reader.is_indexed());
+        reader.seek_to_sequence(22).unwrap();
+        assert_eq!(reader.next_frame().unwrap().unwrap().header.sequence, 21);
+    }
+    
+    #[test]
+    fn cut_off_last_record_ends_the_data() {
+        let mut data = capture(false);
+        data.truncate(data.len() - 5);
+        let mut reader = CaptureReader::new(Cursor::new(data)).unwrap();
+        
+        let frames: Vec<RecordedFrame> = reader.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(frames.len(), 19);
+        assert_eq!(reader.truncated(), 1);
+        assert!(reader.next_frame().unwrap().is_none());
+        assert_eq!(reader.truncated(), 1);
+    }
+}
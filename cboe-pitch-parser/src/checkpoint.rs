@@ -0,0 +1,524 @@
+use crate::{book_manager::BookManager, capture::*, error::*, message::*, order_book::*, parser::PitchParser, replay::FrameSource};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"PITCHBKP";
+const CHECKPOINT_VERSION: u16 = 1;
+
+const NO_UNIT: u16 = 0xFFFF;
+
+/// Result of replaying a feed on top of a restored checkpoint
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CatchUpStats {
+    pub frames_applied: u64,
+    pub frames_skipped: u64, // Entirely at or below the watermark
+    pub messages_applied: u64,
+    pub errors: u64,         // Book update failures while catching up
+}
+
+/// Checkpoint layout, little-endian:
+///
+/// `[magic][version u16][unit count u16]([unit u8][sequence u32])*[book count u32]`
+/// followed by each book as `[symbol][unit u16][status u8][state u8][last update u64]
+/// [order count u32]` and its orders in queue order, bids then asks, each
+/// `[order id u64][side u8][price u64][quantity u32][pid][timestamp u64]`. Then
+/// `[hidden count u32]` undisclosed orders as `[order id u64][side u8][price u64]
+/// [pid][timestamp u64][executed quantity u64][executions u32]`, and the book's
+/// execution stats as six u64 counters in declaration order.
+/// Strings are a length byte then bytes; timestamps are nanoseconds, 0 for none.
+impl BookManager {
+    pub fn write_checkpoint<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        writer.write_u16::<LittleEndian>(CHECKPOINT_VERSION)?;
+        
+        let watermarks: Vec<(u8, u32)> = self.watermarks().collect();
+        writer.write_u16::<LittleEndian>(watermarks.len() as u16)?;
+        for (unit, sequence) in watermarks {
+            writer.write_u8(unit)?;
+            writer.write_u32::<LittleEndian>(sequence)?;
+        }
+        
+        writer.write_u32::<LittleEndian>(self.book_count() as u32)?;
+        for book in self.books() {
+            write_string(writer, book.symbol())?;
+            writer.write_u16::<LittleEndian>(self.unit_of(book.symbol()).map_or(NO_UNIT, u16::from))?;
+            writer.write_u8(book.trading_status().to_byte())?;
+            writer.write_u8(state_to_byte(book.state()))?;
+            writer.write_u64::<LittleEndian>(book.last_update().map_or(0, datetime_to_nanos))?;
+            
+            writer.write_u32::<LittleEndian>(book.order_count() as u32)?;
+            for order in book.orders() {
+                writer.write_u64::<LittleEndian>(order.order_id.0)?;
+                writer.write_u8(order.side.to_byte())?;
+                writer.write_u64::<LittleEndian>(order.price.0)?;
+                writer.write_u32::<LittleEndian>(order.quantity)?;
+                write_string(writer, &order.pid)?;
+                writer.write_u64::<LittleEndian>(datetime_to_nanos(order.timestamp))?;
+            }
+            
+            writer.write_u32::<LittleEndian>(book.hidden_order_count() as u32)?;
+            for order in book.hidden_orders() {
+                writer.write_u64::<LittleEndian>(order.order_id.0)?;
+                writer.write_u8(order.side.to_byte())?;
+                writer.write_u64::<LittleEndian>(order.price.0)?;
+                write_string(writer, &order.pid)?;
+                writer.write_u64::<LittleEndian>(datetime_to_nanos(order.timestamp))?;
+                writer.write_u64::<LittleEndian>(order.executed_quantity)?;
+                writer.write_u32::<LittleEndian>(order.executions)?;
+            }
+            
+            let stats = book.execution_stats();
+            for counter in [
+                stats.displayed_volume,
+                stats.displayed_executions,
+                stats.hidden_volume,
+                stats.hidden_executions,
+                stats.unattributed_volume,
+                stats.unattributed_trades,
+            ] {
+                writer.write_u64::<LittleEndian>(counter)?;
+            }
+        }
+        
+        writer.flush()?;
+        Ok(())
+    }
+    
+    pub fn read_checkpoint<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(PitchError::Parse("Not a book checkpoint".to_string()));
+        }
+        
+        let version = reader.read_u16::<LittleEndian>()?;
+        if version != CHECKPOINT_VERSION {
+            return Err(PitchError::Parse(format!("Unsupported checkpoint version {}", version)));
+        }
+        
+        let mut manager = BookManager::new();
+        
+        let unit_count = reader.read_u16::<LittleEndian>()?;
+        for _ in 0..unit_count {
+            let unit = reader.read_u8()?;
+            let sequence = reader.read_u32::<LittleEndian>()?;
+            manager.set_last_sequence(unit, sequence);
+        }
+        
+        let book_count = reader.read_u32::<LittleEndian>()?;
+        for _ in 0..book_count {
+            let symbol = read_string(reader)?;
+            let unit = match reader.read_u16::<LittleEndian>()? {
+                NO_UNIT => None,
+                unit => Some(unit as u8),
+            };
+            
+            let status_byte = reader.read_u8()?;
+            let trading_status = TradingStatus::from_byte(status_byte)
+                .ok_or_else(|| PitchError::Parse(format!("Invalid trading status: {}", status_byte)))?;
+            let state = state_from_byte(reader.read_u8()?)?;
+            let last_update = match reader.read_u64::<LittleEndian>()? {
+                0 => None,
+                nanos => Some(nanos_to_datetime(nanos)),
+            };
+            
+            let mut book = OrderBook::new(symbol);
+            
+            let order_count = reader.read_u32::<LittleEndian>()?;
+            for _ in 0..order_count {
+                let order_id = OrderId(reader.read_u64::<LittleEndian>()?);
+                let side_byte = reader.read_u8()?;
+                let side = Side::from_byte(side_byte)
+                    .ok_or_else(|| PitchError::Parse(format!("Invalid side: {}", side_byte)))?;
+                
+                // Orders were written in queue order, so re-adding them rebuilds priority
+                book.restore_order(OrderBookEntry {
+                    order_id,
+                    side,
+                    price: Price(reader.read_u64::<LittleEndian>()?),
+                    quantity: reader.read_u32::<LittleEndian>()?,
+                    pid: read_string(reader)?,
+                    timestamp: nanos_to_datetime(reader.read_u64::<LittleEndian>()?),
+                });
+            }
+            
+            let hidden_count = reader.read_u32::<LittleEndian>()?;
+            for _ in 0..hidden_count {
+                let order_id = OrderId(reader.read_u64::<LittleEndian>()?);
+                let side_byte = reader.read_u8()?;
+                let side = Side::from_byte(side_byte)
+                    .ok_or_else(|| PitchError::Parse(format!("Invalid side: {}", side_byte)))?;
+                
+                book.restore_hidden_order(HiddenOrder {
+                    order_id,
+                    side,
+                    price: Price(reader.read_u64::<LittleEndian>()?),
+                    pid: read_string(reader)?,
+                    timestamp: nanos_to_datetime(reader.read_u64::<LittleEndian>()?),
+                    executed_quantity: reader.read_u64::<LittleEndian>()?,
+                    executions: reader.read_u32::<LittleEndian>()?,
+                });
+            }
+            
+            book.restore_execution_stats(ExecutionStats {
+                displayed_volume: reader.read_u64::<LittleEndian>()?,
+                displayed_executions: reader.read_u64::<LittleEndian>()?,
+                hidden_volume: reader.read_u64::<LittleEndian>()?,
+                hidden_executions: reader.read_u64::<LittleEndian>()?,
+                unattributed_volume: reader.read_u64::<LittleEndian>()?,
+                unattributed_trades: reader.read_u64::<LittleEndian>()?,
+            });
+            
+            book.restore_status(trading_status, state, last_update);
+            manager.insert_book(book, unit);
+        }
+        
+        Ok(manager)
+    }
+    
+    /// Write to a temporary file and rename it over `path`, so a crash mid-write
+    /// never leaves a truncated checkpoint behind
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let temp = path.with_extension("tmp");
+        
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        self.write_checkpoint(&mut writer)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+    
+    pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read_checkpoint(&mut BufReader::new(File::open(path)?))
+    }
+    
+    /// Apply only what comes after each unit's watermark; frames at or below it are
+    /// skipped and a frame straddling it is applied from the first new message.
+    ///
+    /// For a single-unit capture, seek the source to `last_sequence(unit) + 1` first
+    /// to avoid reading the skipped frames at all.
+    pub fn catch_up<S: FrameSource>(&mut self, source: &mut S) -> Result<CatchUpStats> {
+        let parser = PitchParser::new();
+        let mut stats = CatchUpStats::default();
+        
+        while let Some(frame) = source.next_frame()? {
+            if frame.header.is_heartbeat() {
+                continue;
+            }
+            
+            let messages = frame.parse(&parser)?;
+            let mut header = frame.header.clone();
+            
+            let already_applied = match self.last_sequence(header.unit) {
+                Some(watermark) if header.sequence <= watermark => {
+                    ((watermark - header.sequence) as usize + 1).min(messages.len())
+                },
+                _ => 0,
+            };
+            
+            if already_applied == messages.len() {
+                stats.frames_skipped += 1;
+                continue;
+            }
+            
+            header.sequence = header.sequence.wrapping_add(already_applied as u32);
+            header.count = (messages.len() - already_applied) as u8;
+            
+            let errors = self.apply_frame(&header, &messages[already_applied..]);
+            stats.errors += errors.len() as u64;
+            stats.frames_applied += 1;
+            stats.messages_applied += (messages.len() - already_applied) as u64;
+        }
+        
+        Ok(stats)
+    }
+}
+
+/// Writes a checkpoint whenever `interval` has passed since the last one
+#[derive(Debug)]
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    last_saved: Option<Instant>,
+    saved: u64,
+}
+
+impl Checkpointer {
+    pub fn new<P: Into<PathBuf>>(path: P, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            last_saved: None,
+            saved: 0,
+        }
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Call between frames; returns true if a checkpoint was written
+    pub fn maybe_save(&mut self, manager: &BookManager, now: Instant) -> Result<bool> {
+        let due = self.last_saved.is_none_or(|last| now.duration_since(last) >= self.interval);
+        if !due {
+            return Ok(false);
+        }
+        
+        manager.save_checkpoint(&self.path)?;
+        self.last_saved = Some(now);
+        self.saved += 1;
+        Ok(true)
+    }
+    
+    pub fn checkpoints_saved(&self) -> u64 {
+        self.saved
+    }
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> Result<()> {
+    // Cut at a character boundary so the stored prefix is still valid UTF-8
+    let bytes = &value.as_bytes()[..value.floor_char_boundary(u8::MAX as usize)];
+    writer.write_u8(bytes.len() as u8)?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String> {
+    let mut bytes = vec![0u8; reader.read_u8()? as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| PitchError::Parse(format!("Invalid string in checkpoint: {}", e)))
+}
+
+fn state_to_byte(state: BookState) -> u8 {
+    match state {
+        BookState::Live => 0,
+        BookState::Cleared => 1,
+        BookState::Recovering => 2,
+        BookState::Final => 3,
+    }
+}
+
+fn state_from_byte(byte: u8) -> Result<BookState> {
+    match byte {
+        0 => Ok(BookState::Live),
+        1 => Ok(BookState::Cleared),
+        2 => Ok(BookState::Recovering),
+        3 => Ok(BookState::Final),
+        other => Err(PitchError::Parse(format!("Invalid book state: {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::RawFrameSource;
+    use crate::simulator::PitchSimulator;
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::io::Cursor;
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn add(order_id: u64, side: Side, quantity: u32, symbol: &str, price: u64) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: at(order_id as i64),
+            order_id: OrderId(order_id),
+            side,
+            quantity,
+            symbol: symbol.to_string(),
+            price: Price(price),
+            pid: "TEST".to_string(),
+        }
+    }
+    
+    fn trade(order_id: u64, quantity: u32, symbol: &str, price: u64) -> PitchMessage {
+        PitchMessage::Trade {
+            timestamp: at(20),
+            symbol: symbol.to_string(),
+            quantity,
+            price: Price(price),
+            execution_id: ExecutionId(1),
+            order_id: OrderId(order_id),
+            contra_order_id: OrderId(0),
+            pid: String::new(),
+            contra_pid: String::new(),
+            trade_type: 'N',
+            trade_designation: ' ',
+            trade_report_type: ' ',
+            trade_transaction_time: at(20),
+            flags: 0,
+        }
+    }
+    
+    fn manager() -> BookManager {
+        let mut manager = BookManager::new();
+        let messages = vec![
+            PitchMessage::TradingStatus {
+                timestamp: at(0),
+                symbol: "AAPL".to_string(),
+                trading_status: TradingStatus::Trading,
+                market_id_code: String::new(),
+            },
+            add(1, Side::Buy, 100, "AAPL", 1_500_000),
+            add(2, Side::Buy, 200, "AAPL", 1_500_000),
+            add(3, Side::Buy, 50, "AAPL", 1_490_000),
+            add(4, Side::Sell, 300, "AAPL", 1_510_000),
+            add(5, Side::Sell, 0, "AAPL", 1_505_000), // Undisclosed
+            add(6, Side::Buy, 10, "MSFT", 3_000_000),
+            PitchMessage::OrderExecuted {
+                timestamp: at(10),
+                order_id: OrderId(1),
+                executed_quantity: 40,
+                execution_id: ExecutionId(2),
+                contra_order_id: OrderId(0),
+                contra_pid: String::new(),
+            },
+            trade(5, 25, "AAPL", 1_505_000),
+        ];
+        let header = SequencedUnitHeader { length: 0, count: messages.len() as u8, unit: 3, sequence: 100 };
+        assert!(manager.apply_frame(&header, &messages).is_empty());
+        manager
+    }
+    
+    fn round_trip(manager: &BookManager) -> BookManager {
+        let mut data = Vec::new();
+        manager.write_checkpoint(&mut data).unwrap();
+        BookManager::read_checkpoint(&mut Cursor::new(data)).unwrap()
+    }
+    
+    #[test]
+    fn round_trip_restores_books_and_watermarks() {
+        let original = manager();
+        let restored = round_trip(&original);
+        
+        assert_eq!(restored.watermarks().collect::<Vec<_>>(), original.watermarks().collect::<Vec<_>>());
+        assert_eq!(restored.book_count(), 2);
+        assert_eq!(restored.order_count(), original.order_count());
+        assert_eq!(restored.unit_of("AAPL"), Some(3));
+        assert_eq!(restored.symbol_for_order(OrderId(6)), Some("MSFT"));
+        
+        for symbol in ["AAPL", "MSFT"] {
+            let (before, after) = (original.book(symbol).unwrap(), restored.book(symbol).unwrap());
+            assert_eq!(after.orders().collect::<Vec<_>>(), before.orders().collect::<Vec<_>>());
+            assert_eq!(after.get_level_info(5), before.get_level_info(5));
+            assert_eq!(after.trading_status(), before.trading_status());
+            assert_eq!(after.state(), before.state());
+            assert_eq!(after.last_update(), before.last_update());
+        }
+    }
+    
+    #[test]
+    fn round_trip_keeps_queue_priority() {
+        let restored = round_trip(&manager());
+        let book = restored.book("AAPL").unwrap();
+        
+        let queue: Vec<(u64, u32)> = book
+            .levels(Side::Buy)
+            .next()
+            .unwrap()
+            .orders()
+            .map(|order| (order.order_id.0, order.quantity))
+            .collect();
+        assert_eq!(queue, vec![(1, 60), (2, 200)]);
+    }
+    
+    #[test]
+    fn round_trip_restores_hidden_orders_and_execution_stats() {
+        let original = manager();
+        let restored = round_trip(&original);
+        let (before, after) = (original.book("AAPL").unwrap(), restored.book("AAPL").unwrap());
+        
+        let hidden = after.hidden_order(OrderId(5)).unwrap();
+        assert_eq!(hidden.executed_quantity, 25);
+        assert_eq!(hidden, before.hidden_order(OrderId(5)).unwrap());
+        assert_eq!(after.execution_stats(), before.execution_stats());
+        assert_eq!(after.execution_stats().displayed_volume, 40);
+        assert_eq!(after.execution_stats().hidden_volume, 25);
+    }
+    
+    #[test]
+    fn rejects_wrong_magic_and_version() {
+        let mut data = Vec::new();
+        manager().write_checkpoint(&mut data).unwrap();
+        
+        let mut wrong_magic = data.clone();
+        wrong_magic[0] = b'X';
+        assert!(BookManager::read_checkpoint(&mut Cursor::new(wrong_magic)).is_err());
+        
+        let mut wrong_version = data;
+        wrong_version[8..10].copy_from_slice(&(CHECKPOINT_VERSION + 1).to_le_bytes());
+        assert!(BookManager::read_checkpoint(&mut Cursor::new(wrong_version)).is_err());
+    }
+    
+    #[test]
+    fn rejects_truncated_checkpoint() {
+        let mut data = Vec::new();
+        manager().write_checkpoint(&mut data).unwrap();
+        data.truncate(data.len() - 1);
+        
+        assert!(BookManager::read_checkpoint(&mut Cursor::new(data)).is_err());
+    }
+    
+    #[test]
+    fn long_strings_are_cut_on_a_character_boundary() {
+        // 127 two-byte characters fill 254 bytes; the next one would straddle the limit
+        let symbol = "é".repeat(200);
+        let mut manager = BookManager::new();
+        manager.apply_message(&add(1, Side::Buy, 100, &symbol, 1_000)).unwrap();
+        
+        let restored = round_trip(&manager);
+        
+        assert_eq!(restored.symbols().collect::<Vec<_>>(), vec!["é".repeat(127)]);
+    }
+    
+    #[test]
+    fn catch_up_resumes_after_the_watermark() {
+        let mut manager = manager();
+        assert_eq!(manager.last_sequence(3), Some(108));
+        
+        let delete = |order_id: u64| PitchMessage::DeleteOrder { timestamp: at(30), order_id: OrderId(order_id) };
+        let frames = [
+            // Entirely at or below the watermark
+            (3, 95, vec![delete(3), delete(4)]),
+            // Straddles it: 107 and 108 were already applied
+            (3, 107, vec![delete(1), delete(3), add(7, Side::Buy, 70, "AAPL", 1_480_000), delete(2)]),
+            (3, 111, vec![add(8, Side::Sell, 80, "AAPL", 1_520_000)]),
+            // A unit the checkpoint never saw
+            (4, 1, vec![add(9, Side::Buy, 90, "IBM", 1_000_000)]),
+        ];
+        
+        let simulator = PitchSimulator::new();
+        let mut data = Vec::new();
+        for (unit, sequence, messages) in frames {
+            let header = SequencedUnitHeader { length: 0, count: messages.len() as u8, unit, sequence };
+            data.extend(simulator.serialize_frame(&header, &messages).unwrap());
+        }
+        
+        let stats = manager.catch_up(&mut RawFrameSource::new(data)).unwrap();
+        
+        assert_eq!(stats, CatchUpStats {
+            frames_applied: 3,
+            frames_skipped: 1,
+            messages_applied: 4,
+            errors: 0,
+        });
+        
+        // The straddling frame's already-applied deletes were not replayed
+        let book = manager.book("AAPL").unwrap();
+        assert!(book.contains_order(OrderId(1)));
+        assert!(book.contains_order(OrderId(3)));
+        assert!(book.contains_order(OrderId(4)));
+        assert!(book.contains_order(OrderId(7)));
+        assert!(book.contains_order(OrderId(8)));
+        assert!(!book.contains_order(OrderId(2)));
+        assert_eq!(manager.last_sequence(3), Some(111));
+        assert_eq!(manager.last_sequence(4), Some(1));
+        assert_eq!(manager.symbol_for_order(OrderId(9)), Some("IBM"));
+    }
+}
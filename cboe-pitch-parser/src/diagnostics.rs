@@ -0,0 +1,37 @@
+use crate::{message::*, order_book::OrderBook};
+use serde::{Deserialize, Serialize};
+
+/// A broken book invariant, naming the orders or levels involved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiagnosticIssue {
+    ZeroQuantity(OrderId),
+    OrphanedOrder(OrderId),        // Queued at a level but missing from the order index
+    UnqueuedOrder(OrderId),        // In the order index but not queued at any level
+    DuplicateOrder(OrderId),       // Queued more than once
+    MisplacedOrder {
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        level_side: Side,
+        level_price: Price,
+    },
+    BrokenQueue { side: Side, price: Price }, // Queue links are inconsistent
+    LevelQuantityMismatch { side: Side, price: Price, cached: u64, actual: u64 },
+    LevelCountMismatch { side: Side, price: Price, cached: usize, actual: usize },
+    SideQuantityMismatch { side: Side, cached: u64, actual: u64 },
+    BestPriceMismatch { side: Side, cached: Option<Price>, actual: Option<Price> },
+    CrossedMarket { bid: Price, ask: Price },
+    LockedMarket { price: Price },
+}
+
+/// An issue found in one symbol's book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookDiagnostic {
+    pub symbol: String,
+    pub issue: DiagnosticIssue,
+}
+
+/// Check every invariant of `book`; empty when the book is consistent
+pub fn diagnose_order_book(book: &OrderBook) -> Vec<DiagnosticIssue> {
+    book.diagnose()
+}
@@ -1,3 +1,4 @@
+use crate::message::{OrderId, TradingStatus};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -16,6 +17,18 @@ pub enum PitchError {
     
     #[error("Parse error: {0}")]
     Parse(String),
+    
+    #[error("Configuration error: {0}")]
+    Config(String),
+    
+    #[error("Unknown order: {0:?}")]
+    UnknownOrder(OrderId),
+    
+    #[error("Order {order_id:?} has {resting} resting, cannot remove {requested}")]
+    QuantityExceeded { order_id: OrderId, resting: u32, requested: u32 },
+    
+    #[error("Illegal trading status transition for {symbol}: {from:?} -> {to:?}")]
+    IllegalTransition { symbol: String, from: TradingStatus, to: TradingStatus },
 }
 
 pub type Result<T> = std::result::Result<T, PitchError>;
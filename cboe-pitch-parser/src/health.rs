@@ -0,0 +1,358 @@
+use crate::message::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthConfig {
+    pub stale_after: Duration,       // Silence after a data frame before flagging stale
+    pub heartbeat_timeout: Duration, // Silence after a heartbeat before flagging stale
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            stale_after: Duration::from_secs(5),
+            heartbeat_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthStatus {
+    Waiting, // Watched but nothing received yet
+    Healthy,
+    Stale,
+    Closed,  // End of Session received, silence is expected
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HealthEvent {
+    Stale { unit: u8, silent_for: Duration },
+    Recovered { unit: u8, silent_for: Duration },
+    SessionEnded { unit: u8 },
+    SessionStarted { unit: u8 },
+    Gap { unit: u8, sequence: u32, count: u32 }, // First missing sequence and how many
+}
+
+/// Snapshot of one unit for health-check endpoints
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitHealthReport {
+    pub unit: u8,
+    pub status: HealthStatus,
+    pub since_last_frame: Option<Duration>,
+    pub since_last_heartbeat: Option<Duration>,
+    pub frames: u64,
+    pub heartbeats: u64,
+    pub stale_events: u64,
+    pub gaps: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedHealthReport {
+    pub status: HealthStatus, // Worst status across units
+    pub units: Vec<UnitHealthReport>,
+}
+
+impl FeedHealthReport {
+    pub fn is_healthy(&self) -> bool {
+        matches!(self.status, HealthStatus::Healthy | HealthStatus::Closed)
+    }
+}
+
+#[derive(Debug)]
+struct UnitHealth {
+    status: HealthStatus,
+    watched_since: Instant,
+    last_frame: Option<Instant>,
+    last_heartbeat: Option<Instant>,
+    idle: bool,                 // Last frame was a heartbeat
+    next_sequence: Option<u32>, // Heartbeats carry the next sequence, so they reveal gaps too
+    frames: u64,
+    heartbeats: u64,
+    stale_events: u64,
+    gaps: u64,
+}
+
+impl UnitHealth {
+    fn new(now: Instant) -> Self {
+        Self {
+            status: HealthStatus::Waiting,
+            watched_since: now,
+            last_frame: None,
+            last_heartbeat: None,
+            idle: false,
+            next_sequence: None,
+            frames: 0,
+            heartbeats: 0,
+            stale_events: 0,
+            gaps: 0,
+        }
+    }
+    
+    fn silent_for(&self, now: Instant) -> Duration {
+        now.duration_since(self.last_frame.unwrap_or(self.watched_since))
+    }
+}
+
+/// Watches per-unit frame and heartbeat arrival and flags silent units
+#[derive(Debug)]
+pub struct FeedHealthMonitor {
+    config: HealthConfig,
+    units: BTreeMap<u8, UnitHealth>,
+}
+
+impl FeedHealthMonitor {
+    pub fn new(config: HealthConfig) -> Self {
+        Self {
+            config,
+            units: BTreeMap::new(),
+        }
+    }
+    
+    /// Start monitoring a unit before its first frame arrives
+    pub fn watch(&mut self, unit: u8, now: Instant) {
+        self.units.entry(unit).or_insert_with(|| UnitHealth::new(now));
+    }
+    
+    /// Record a received frame; returns any transition it caused
+    pub fn on_frame(&mut self, header: &SequencedUnitHeader, messages: &[PitchMessage], now: Instant) -> Vec<HealthEvent> {
+        let unit = header.unit;
+        let health = self.units.entry(unit).or_insert_with(|| UnitHealth::new(now));
+        let mut events = Vec::new();
+        
+        if health.status == HealthStatus::Stale {
+            events.push(HealthEvent::Recovered {
+                unit,
+                silent_for: health.silent_for(now),
+            });
+        }
+        
+        health.frames += 1;
+        health.last_frame = Some(now);
+        health.idle = header.is_heartbeat();
+        
+        if health.idle {
+            health.heartbeats += 1;
+            health.last_heartbeat = Some(now);
+        }
+        
+        let session_ended = messages.iter().any(|message| matches!(message, PitchMessage::EndOfSession { .. }));
+        
+        if session_ended {
+            health.status = HealthStatus::Closed;
+            events.push(HealthEvent::SessionEnded { unit });
+        } else if health.status == HealthStatus::Closed {
+            // Heartbeats may continue after close; only new data reopens the unit
+            if !header.is_heartbeat() {
+                health.status = HealthStatus::Healthy;
+                health.next_sequence = None; // A new session restarts its sequences
+                events.push(HealthEvent::SessionStarted { unit });
+            }
+        } else {
+            health.status = HealthStatus::Healthy;
+        }
+        
+        if let Some(expected) = health.next_sequence.filter(|&expected| header.sequence > expected) {
+            health.gaps += 1;
+            events.push(HealthEvent::Gap {
+                unit,
+                sequence: expected,
+                count: header.sequence - expected,
+            });
+        }
+        
+        // Retransmissions and duplicates never move the expectation back
+        let end = header.sequence.wrapping_add(header.count as u32);
+        health.next_sequence = Some(health.next_sequence.map_or(end, |expected| expected.max(end)));
+        
+        events
+    }
+    
+    /// Raise stale events for units that have gone quiet; call periodically
+    pub fn check(&mut self, now: Instant) -> Vec<HealthEvent> {
+        let mut events = Vec::new();
+        
+        for (&unit, health) in self.units.iter_mut() {
+            if matches!(health.status, HealthStatus::Stale | HealthStatus::Closed) {
+                continue;
+            }
+            
+            if Self::is_stale(&self.config, health, now) {
+                health.status = HealthStatus::Stale;
+                health.stale_events += 1;
+                events.push(HealthEvent::Stale {
+                    unit,
+                    silent_for: health.silent_for(now),
+                });
+            }
+        }
+        
+        events
+    }
+    
+    fn is_stale(config: &HealthConfig, health: &UnitHealth, now: Instant) -> bool {
+        // An idle unit is kept alive by heartbeats, so a late one is noticed sooner
+        let threshold = if health.idle {
+            config.heartbeat_timeout
+        } else {
+            config.stale_after
+        };
+        
+        health.silent_for(now) >= threshold
+    }
+    
+    pub fn unit_status(&self, unit: u8) -> Option<HealthStatus> {
+        self.units.get(&unit).map(|health| health.status)
+    }
+    
+    pub fn report(&self, now: Instant) -> FeedHealthReport {
+        let units: Vec<UnitHealthReport> = self.units
+            .iter()
+            .map(|(&unit, health)| UnitHealthReport {
+                unit,
+                status: health.status,
+                since_last_frame: health.last_frame.map(|at| now.duration_since(at)),
+                since_last_heartbeat: health.last_heartbeat.map(|at| now.duration_since(at)),
+                frames: health.frames,
+                heartbeats: health.heartbeats,
+                stale_events: health.stale_events,
+                gaps: health.gaps,
+            })
+            .collect();
+        
+        let status = units
+            .iter()
+            .map(|report| report.status)
+            .max_by_key(|status| match status {
+                HealthStatus::Closed => 0,
+                HealthStatus::Healthy => 1,
+                HealthStatus::Waiting => 2,
+                HealthStatus::Stale => 3,
+            })
+            .unwrap_or(HealthStatus::Waiting);
+        
+        FeedHealthReport { status, units }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    
+    fn header(unit: u8, sequence: u32, count: u8) -> SequencedUnitHeader {
+        SequencedUnitHeader { length: 0, count, unit, sequence }
+    }
+    
+    fn deletes(count: u8) -> Vec<PitchMessage> {
+        (0..count as u64)
+            .map(|i| PitchMessage::DeleteOrder {
+                timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000),
+                order_id: OrderId(i),
+            })
+            .collect()
+    }
+    
+    fn monitor() -> FeedHealthMonitor {
+        FeedHealthMonitor::new(HealthConfig {
+            stale_after: Duration::from_secs(5),
+            heartbeat_timeout: Duration::from_secs(3),
+        })
+    }
+    
+    #[test]
+    fn silence_after_data_is_stale_then_recovers() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        
+        assert!(monitor.on_frame(&header(1, 1, 2), &deletes(2), start).is_empty());
+        assert_eq!(monitor.unit_status(1), Some(HealthStatus::Healthy));
+        assert!(monitor.check(start + Duration::from_secs(4)).is_empty());
+        
+        let events = monitor.check(start + Duration::from_secs(5));
+        assert_eq!(events, vec![HealthEvent::Stale { unit: 1, silent_for: Duration::from_secs(5) }]);
+        assert!(monitor.check(start + Duration::from_secs(6)).is_empty(), "stale is raised once");
+        assert!(!monitor.report(start + Duration::from_secs(6)).is_healthy());
+        
+        let events = monitor.on_frame(&header(1, 3, 1), &deletes(1), start + Duration::from_secs(7));
+        assert_eq!(events, vec![HealthEvent::Recovered { unit: 1, silent_for: Duration::from_secs(7) }]);
+        assert_eq!(monitor.report(start + Duration::from_secs(7)).units[0].stale_events, 1);
+    }
+    
+    #[test]
+    fn heartbeat_only_unit_stays_healthy() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        
+        monitor.on_frame(&header(1, 1, 1), &deletes(1), start);
+        for second in 1..=20 {
+            let now = start + Duration::from_secs(second);
+            assert!(monitor.on_frame(&header(1, 2, 0), &[], now).is_empty());
+            assert!(monitor.check(now + Duration::from_millis(999)).is_empty());
+        }
+        
+        let report = monitor.report(start + Duration::from_secs(20));
+        assert!(report.is_healthy());
+        assert_eq!((report.units[0].frames, report.units[0].heartbeats, report.units[0].gaps), (21, 20, 0));
+        
+        // A missed heartbeat is noticed at the shorter heartbeat timeout
+        let events = monitor.check(start + Duration::from_secs(23));
+        assert!(matches!(events[..], [HealthEvent::Stale { unit: 1, .. }]));
+    }
+    
+    #[test]
+    fn sequence_gaps_are_reported_by_data_and_heartbeats() {
+        let mut monitor = monitor();
+        let now = Instant::now();
+        
+        monitor.on_frame(&header(1, 1, 2), &deletes(2), now);
+        assert_eq!(
+            monitor.on_frame(&header(1, 5, 1), &deletes(1), now),
+            vec![HealthEvent::Gap { unit: 1, sequence: 3, count: 2 }],
+        );
+        // The heartbeat says 6 and 7 were sent
+        assert_eq!(
+            monitor.on_frame(&header(1, 8, 0), &[], now),
+            vec![HealthEvent::Gap { unit: 1, sequence: 6, count: 2 }],
+        );
+        // A late retransmission neither opens a gap nor rewinds the expectation
+        assert!(monitor.on_frame(&header(1, 3, 2), &deletes(2), now).is_empty());
+        assert!(monitor.on_frame(&header(1, 8, 1), &deletes(1), now).is_empty());
+        
+        assert_eq!(monitor.report(now).units[0].gaps, 2);
+    }
+    
+    #[test]
+    fn silence_after_end_of_session_is_not_stale() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        let end = [PitchMessage::EndOfSession { timestamp: Utc.timestamp_nanos(0) }];
+        
+        monitor.on_frame(&header(1, 1, 1), &deletes(1), start);
+        assert_eq!(monitor.on_frame(&header(1, 2, 1), &end, start), vec![HealthEvent::SessionEnded { unit: 1 }]);
+        assert!(monitor.check(start + Duration::from_secs(3600)).is_empty());
+        assert_eq!(monitor.unit_status(1), Some(HealthStatus::Closed));
+        
+        // Heartbeats keep it closed, new data opens a new session with fresh sequences
+        assert!(monitor.on_frame(&header(1, 3, 0), &[], start + Duration::from_secs(3600)).is_empty());
+        let events = monitor.on_frame(&header(1, 1, 1), &deletes(1), start + Duration::from_secs(3601));
+        assert_eq!(events, vec![HealthEvent::SessionStarted { unit: 1 }]);
+    }
+    
+    #[test]
+    fn report_takes_the_worst_unit() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        
+        monitor.watch(2, start);
+        monitor.on_frame(&header(1, 1, 1), &deletes(1), start);
+        assert_eq!(monitor.report(start).status, HealthStatus::Waiting);
+        
+        // A watched unit that never sends goes stale too
+        monitor.on_frame(&header(1, 2, 0), &[], start + Duration::from_secs(4));
+        let events = monitor.check(start + Duration::from_secs(5));
+        assert_eq!(events, vec![HealthEvent::Stale { unit: 2, silent_for: Duration::from_secs(5) }]);
+        assert_eq!(monitor.report(start + Duration::from_secs(5)).status, HealthStatus::Stale);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{capture::*, error::*, message::*, order_book::*, parser::PitchParser};
+use chrono::{DateTime, Utc};
+use std::io::{Read, Seek};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryConfig {
+    pub snapshot_interval: u64, // Messages between snapshots, taken at the next frame boundary
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_interval: 50_000,
+        }
+    }
+}
+
+/// The book as of the end of a frame
+#[derive(Debug, Clone)]
+struct HistorySnapshot {
+    sequence: u32,               // Last message applied
+    time: Option<DateTime<Utc>>, // Latest exchange timestamp applied
+    book: OrderBook,
+}
+
+/// One symbol's book over a recorded session, materialised at any time or sequence
+/// by replaying forward from the nearest earlier snapshot.
+///
+/// Built from a single-unit capture; orders added before the capture starts are unknown,
+/// so messages referring to them are skipped.
+pub struct BookHistory<R: Read + Seek> {
+    reader: CaptureReader<R>,
+    parser: PitchParser,
+    symbol: String,
+    snapshots: Vec<HistorySnapshot>, // Ascending sequence and time
+    errors: u64,
+}
+
+impl<R: Read + Seek> BookHistory<R> {
+    /// Replay the whole capture once, keeping a snapshot every `snapshot_interval` messages
+    pub fn build(mut reader: CaptureReader<R>, symbol: &str, config: HistoryConfig) -> Result<Self> {
+        let parser = PitchParser::new();
+        let mut book = OrderBook::new(symbol.to_string());
+        let mut snapshots = vec![HistorySnapshot {
+            sequence: 0,
+            time: None,
+            book: book.clone(),
+        }];
+        let mut errors = 0;
+        let mut time = None;
+        let mut since_snapshot = 0;
+        
+        reader.rewind()?;
+        while let Some(frame) = reader.next_frame()? {
+            if frame.header.is_heartbeat() {
+                continue;
+            }
+            
+            let messages = frame.parse(&parser)?;
+            for message in &messages {
+                if let Some(timestamp) = message.exchange_timestamp() {
+                    time = Some(timestamp);
+                }
+                if apply_to_book(&mut book, message).is_err() {
+                    errors += 1;
+                }
+            }
+            
+            since_snapshot += messages.len() as u64;
+            if since_snapshot >= config.snapshot_interval {
+                since_snapshot = 0;
+                snapshots.push(HistorySnapshot {
+                    sequence: last_sequence(&frame.header, messages.len()),
+                    time,
+                    book: book.clone(),
+                });
+            }
+        }
+        
+        Ok(Self {
+            reader,
+            parser,
+            symbol: symbol.to_string(),
+            snapshots,
+            errors,
+        })
+    }
+    
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+    
+    pub fn snapshot_count(&self) -> usize {
+        self.snapshots.len()
+    }
+    
+    /// Book update failures seen while building, such as executions beyond resting size
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+    
+    /// The book after every message up to and including `sequence`
+    pub fn book_at_sequence(&mut self, sequence: u32) -> Result<OrderBook> {
+        let index = self.snapshots.partition_point(|snapshot| snapshot.sequence <= sequence) - 1;
+        self.replay_from(index, |message_sequence, _| message_sequence <= sequence)
+    }
+    
+    /// The book after every message timestamped at or before `time`
+    pub fn book_at_time(&mut self, time: DateTime<Utc>) -> Result<OrderBook> {
+        let index = self.snapshots.partition_point(|snapshot| snapshot.time.is_none_or(|t| t <= time)) - 1;
+        self.replay_from(index, |_, message| message.exchange_timestamp().is_none_or(|timestamp| timestamp <= time))
+    }
+    
+    /// Top `levels` bid and ask levels at `time`, as from `OrderBook::get_level_info`
+    pub fn depth_at_time(&mut self, time: DateTime<Utc>, levels: usize) -> Result<(LevelInfo, LevelInfo)> {
+        Ok(self.book_at_time(time)?.get_level_info(levels))
+    }
+    
+    /// Replay from a snapshot while `include` accepts each message
+    fn replay_from<F>(&mut self, index: usize, include: F) -> Result<OrderBook>
+    where
+        F: Fn(u32, &PitchMessage) -> bool,
+    {
+        let snapshot = &self.snapshots[index];
+        let mut book = snapshot.book.clone();
+        let after = snapshot.sequence;
+        
+        match index {
+            0 => self.reader.rewind()?,
+            _ => self.reader.seek_to_sequence(after.wrapping_add(1))?,
+        }
+        
+        while let Some(frame) = self.reader.next_frame()? {
+            if frame.header.is_heartbeat() {
+                continue;
+            }
+            
+            for (offset, message) in frame.parse(&self.parser)?.iter().enumerate() {
+                let sequence = frame.header.sequence.wrapping_add(offset as u32);
+                if index > 0 && sequence <= after {
+                    continue;
+                }
+                if !include(sequence, message) {
+                    return Ok(book);
+                }
+                // Failures were already counted while building
+                let _ = apply_to_book(&mut book, message);
+            }
+        }
+        
+        Ok(book)
+    }
+}
+
+fn last_sequence(header: &SequencedUnitHeader, count: usize) -> u32 {
+    header.sequence.wrapping_add(count as u32).wrapping_sub(1)
+}
+
+/// Apply order-keyed messages only for orders this book holds
+fn apply_to_book(book: &mut OrderBook, message: &PitchMessage) -> Result<()> {
+    match message {
+        PitchMessage::OrderExecuted { order_id, .. }
+        | PitchMessage::OrderExecutedAtPrice { order_id, .. }
+        | PitchMessage::ReduceSize { order_id, .. }
+        | PitchMessage::ModifyOrder { order_id, .. }
+        | PitchMessage::DeleteOrder { order_id, .. }
+            if !book.contains_order(*order_id) && !book.contains_hidden_order(*order_id) =>
+        {
+            Ok(())
+        },
+        _ => book.apply_message(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{receiver::Feed, simulator::PitchSimulator};
+    use chrono::{NaiveDate, TimeZone};
+    use std::io::Cursor;
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn add_frame(sequence: u32) -> Vec<u8> {
+        let message = PitchMessage::AddOrder {
+            timestamp: at(sequence as i64),
+            order_id: OrderId(sequence as u64),
+            side: Side::Buy,
+            quantity: 100,
+            symbol: "AAPL".to_string(),
+            price: Price(1_000_000 + sequence as u64),
+            pid: "TEST".to_string(),
+        };
+        let header = SequencedUnitHeader { length: 0, count: 1, unit: 1, sequence };
+        PitchSimulator::new().serialize_frame(&header, &[message]).unwrap()
+    }
+    
+    /// An Order Executed at Price frame, which the parser decodes with the local clock
+    fn executed_at_price_frame(sequence: u32) -> Vec<u8> {
+        let mut message = vec![0u8; 52];
+        message[0] = 52;
+        message[1] = 0x58;
+        message[2..10].copy_from_slice(&(at(sequence as i64).timestamp_nanos_opt().unwrap() as u64).to_le_bytes());
+        
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&(8 + message.len() as u16).to_le_bytes());
+        frame.extend_from_slice(&[1, 1]);
+        frame.extend_from_slice(&sequence.to_le_bytes());
+        frame.extend_from_slice(&message);
+        frame
+    }
+    
+    /// Adds at sequences 1 to 10, one second apart, with an Order Executed at Price at 4
+    fn history(snapshot_interval: u64) -> BookHistory<Cursor<Vec<u8>>> {
+        let header = CaptureHeader::new(1, Feed::A, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        let mut writer = CaptureWriter::new(Cursor::new(Vec::new()), &header).unwrap().with_index_interval(2);
+        
+        for sequence in 1..=10 {
+            let frame = match sequence {
+                4 => executed_at_price_frame(sequence),
+                _ => add_frame(sequence),
+            };
+            writer.write_frame(at(sequence as i64), &frame).unwrap();
+        }
+        
+        let reader = CaptureReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        BookHistory::build(reader, "AAPL", HistoryConfig { snapshot_interval }).unwrap()
+    }
+    
+    fn order_ids(book: &OrderBook) -> Vec<u64> {
+        let mut ids: Vec<u64> = book.orders().map(|order| order.order_id.0).collect();
+        ids.sort();
+        ids
+    }
+    
+    #[test]
+    fn book_at_sequence_matches_full_replay() {
+        for interval in [1, 3, 1_000] {
+            let mut history = history(interval);
+            
+            assert_eq!(order_ids(&history.book_at_sequence(6).unwrap()), vec![1, 2, 3, 5, 6]);
+            assert_eq!(order_ids(&history.book_at_sequence(10).unwrap()).len(), 9);
+            assert!(history.book_at_sequence(0).unwrap().orders().next().is_none());
+        }
+    }
+    
+    #[test]
+    fn book_at_time_is_not_cut_short_by_locally_stamped_messages() {
+        for interval in [1, 3, 1_000] {
+            let mut history = history(interval);
+            
+            assert_eq!(order_ids(&history.book_at_time(at(6)).unwrap()), vec![1, 2, 3, 5, 6], "interval {}", interval);
+            assert_eq!(order_ids(&history.book_at_time(at(7) - chrono::Duration::nanoseconds(1)).unwrap()).len(), 5);
+            assert_eq!(order_ids(&history.book_at_time(at(100)).unwrap()).len(), 9);
+        }
+    }
+    
+    #[test]
+    fn snapshot_times_only_use_exchange_timestamps() {
+        let history = history(1);
+        
+        assert_eq!(history.snapshot_count(), 11);
+        assert!(history.snapshots.iter().all(|snapshot| snapshot.time.is_none_or(|time| time <= at(10))));
+        assert_eq!(history.snapshots[4].time, Some(at(3)));
+    }
+}
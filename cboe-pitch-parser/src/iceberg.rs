@@ -0,0 +1,588 @@
+use crate::{error::*, message::*, order_book::*};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcebergConfig {
+    pub refill_window: Duration,  // Longest gap between a full fill and its refill Add
+    pub min_link_confidence: f64, // Weaker fill/refill pairs are not linked
+    pub min_confidence: f64,      // Icebergs below this do not count towards reserves
+}
+
+impl Default for IcebergConfig {
+    fn default() -> Self {
+        Self {
+            refill_window: Duration::from_millis(5),
+            min_link_confidence: 0.2,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IcebergState {
+    Resting,        // The current clip is in the book
+    AwaitingRefill, // The current clip was fully filled within the refill window
+    Finished,       // Cancelled, moved, out of reserve, or no refill arrived
+}
+
+/// A chain of clips believed to be one order refilling its displayed size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Iceberg {
+    pub id: usize,
+    pub side: Side,
+    pub price: Price,
+    pub order_ids: Vec<OrderId>, // Every clip, oldest first
+    pub display_size: u32,       // Size of the first clip
+    pub executed_quantity: u64,
+    pub resting_quantity: u32,   // Left on the current clip
+    pub confidence: f64,
+    pub state: IcebergState,
+    pub final_clip: bool,        // A refill smaller than the display size drained the reserve
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl Iceberg {
+    pub fn refills(&self) -> usize {
+        self.order_ids.len() - 1
+    }
+    
+    pub fn current_order(&self) -> Option<OrderId> {
+        match self.state {
+            IcebergState::Resting => self.order_ids.last().copied(),
+            _ => None,
+        }
+    }
+    
+    /// The feed never shows the reserve, so a live iceberg is assumed to hold at
+    /// least one more clip unless its last refill was a remainder
+    pub fn suspected_reserve(&self) -> u64 {
+        match self.state {
+            IcebergState::Finished => 0,
+            _ if self.final_clip => 0,
+            _ => self.display_size as u64,
+        }
+    }
+    
+    /// Quantity seen so far plus the suspected reserve
+    pub fn estimated_total_size(&self) -> u64 {
+        self.executed_quantity + self.resting_quantity as u64 + self.suspected_reserve()
+    }
+}
+
+/// Suspected undisplayed quantity behind one price level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelReserve {
+    pub side: Side,
+    pub price: Price,
+    pub icebergs: usize,
+    pub suspected_reserve: u64,
+    pub confidence: f64, // Of the most likely iceberg at the level
+}
+
+/// A visible order the analyzer has seen added
+#[derive(Debug, Clone)]
+struct TrackedOrder {
+    display: u32,
+    executed: u64,
+    iceberg: Option<usize>,
+}
+
+/// A fully filled order that may be refilled by a new Add
+#[derive(Debug, Clone)]
+struct PendingRefill {
+    side: Side,
+    price: Price,
+    order_id: OrderId,
+    pid: String,
+    display: u32,
+    executed: u64,
+    iceberg: Option<usize>,
+    filled_at: DateTime<Utc>,
+}
+
+/// Links full executions and same-price re-adds into logical iceberg orders.
+///
+/// Feed each message to `observe` before the book applies it, since a fill is only
+/// recognised as full while the order is still resting, or use `apply` to do both.
+#[derive(Debug, Clone, Default)]
+pub struct IcebergAnalyzer {
+    config: IcebergConfig,
+    orders: HashMap<OrderId, TrackedOrder>,
+    pending: VecDeque<PendingRefill>, // In arrival order
+    icebergs: Vec<Iceberg>,
+}
+
+impl IcebergAnalyzer {
+    pub fn new(config: IcebergConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+    
+    /// Observe `message` and then apply it to `book`
+    pub fn apply(&mut self, book: &mut OrderBook, message: &PitchMessage) -> Result<()> {
+        self.observe(book, message);
+        book.apply_message(message)
+    }
+    
+    pub fn observe(&mut self, book: &OrderBook, message: &PitchMessage) {
+        // Only exchange times move the expiry clock; some messages decode with the local clock
+        if let Some(now) = message.exchange_timestamp() {
+            self.expire(now);
+        }
+        
+        match message {
+            PitchMessage::UnitClear { .. } => {
+                for iceberg in &mut self.icebergs {
+                    iceberg.state = IcebergState::Finished;
+                }
+                self.orders.clear();
+                self.pending.clear();
+            },
+            PitchMessage::AddOrder { order_id, side, quantity, symbol, price, pid, timestamp }
+                if symbol == book.symbol() && *quantity > 0 =>
+            {
+                let iceberg = self.link_refill(*order_id, *side, *price, *quantity, pid, *timestamp);
+                self.orders.insert(*order_id, TrackedOrder {
+                    display: *quantity,
+                    executed: 0,
+                    iceberg,
+                });
+            },
+            PitchMessage::OrderExecuted { order_id, executed_quantity, .. }
+            | PitchMessage::OrderExecutedAtPrice { order_id, executed_quantity, .. } => {
+                if let Some(order) = book.order(*order_id) {
+                    self.record_execution(order, *executed_quantity, message.exchange_timestamp());
+                }
+            },
+            PitchMessage::ReduceSize { order_id, cancelled_quantity, timestamp } => {
+                if let Some(index) = self.orders.get(order_id).and_then(|order| order.iceberg) {
+                    let iceberg = &mut self.icebergs[index];
+                    iceberg.resting_quantity = iceberg.resting_quantity.saturating_sub(*cancelled_quantity);
+                    iceberg.last_seen = *timestamp;
+                }
+            },
+            PitchMessage::ModifyOrder { order_id, quantity, price, timestamp } => {
+                let Some(order) = self.orders.get_mut(order_id) else {
+                    return;
+                };
+                let iceberg = order.iceberg;
+                // Whether a later full fill looks like a whole clip depends on the new size
+                order.display = *quantity;
+                
+                // A repriced clip loses priority and no longer behaves like a refilling order
+                let moved = iceberg.is_some_and(|index| self.icebergs[index].price != *price);
+                if *quantity == 0 || moved {
+                    self.orders.remove(order_id);
+                }
+                
+                if let Some(index) = iceberg {
+                    let iceberg = &mut self.icebergs[index];
+                    iceberg.last_seen = *timestamp;
+                    if *quantity == 0 || moved {
+                        iceberg.state = IcebergState::Finished;
+                        iceberg.resting_quantity = 0;
+                    } else {
+                        iceberg.resting_quantity = *quantity;
+                    }
+                }
+            },
+            PitchMessage::DeleteOrder { order_id, timestamp } => {
+                if let Some(index) = self.orders.remove(order_id).and_then(|order| order.iceberg) {
+                    let iceberg = &mut self.icebergs[index];
+                    iceberg.state = IcebergState::Finished;
+                    iceberg.resting_quantity = 0;
+                    iceberg.last_seen = *timestamp;
+                }
+            },
+            _ => {},
+        }
+    }
+    
+    /// Every iceberg found so far, finished ones included
+    pub fn icebergs(&self) -> &[Iceberg] {
+        &self.icebergs
+    }
+    
+    /// Icebergs still resting or awaiting a refill
+    pub fn active_icebergs(&self) -> impl Iterator<Item = &Iceberg> {
+        self.icebergs.iter().filter(|iceberg| iceberg.state != IcebergState::Finished)
+    }
+    
+    pub fn iceberg_for_order(&self, order_id: OrderId) -> Option<&Iceberg> {
+        let index = self.orders.get(&order_id)?.iceberg?;
+        self.icebergs.get(index)
+    }
+    
+    /// Suspected reserve at one level, from active icebergs above `min_confidence`
+    pub fn reserve_at(&self, side: Side, price: Price) -> Option<LevelReserve> {
+        self.level_reserves().into_iter().find(|reserve| reserve.side == side && reserve.price == price)
+    }
+    
+    /// Suspected reserves per level, bids then asks, best price first
+    pub fn level_reserves(&self) -> Vec<LevelReserve> {
+        let mut levels: HashMap<(Side, u64), LevelReserve> = HashMap::new();
+        
+        for iceberg in self.active_icebergs().filter(|iceberg| iceberg.confidence >= self.config.min_confidence) {
+            let level = levels.entry((iceberg.side, iceberg.price.0)).or_insert(LevelReserve {
+                side: iceberg.side,
+                price: iceberg.price,
+                icebergs: 0,
+                suspected_reserve: 0,
+                confidence: 0.0,
+            });
+            level.icebergs += 1;
+            level.suspected_reserve += iceberg.suspected_reserve();
+            level.confidence = level.confidence.max(iceberg.confidence);
+        }
+        
+        let mut levels: Vec<LevelReserve> = levels.into_values().collect();
+        levels.sort_by_key(|level| match level.side {
+            Side::Buy => (0, u64::MAX - level.price.0),
+            Side::Sell => (1, level.price.0),
+        });
+        levels
+    }
+    
+    /// `resting` is the book's order before the execution is applied. Without an
+    /// exchange time a full fill cannot be timed against a refill, so it ends the chain.
+    fn record_execution(&mut self, resting: &OrderBookEntry, quantity: u32, timestamp: Option<DateTime<Utc>>) {
+        let order_id = resting.order_id;
+        let Some(order) = self.orders.get_mut(&order_id) else {
+            return;
+        };
+        order.executed += quantity as u64;
+        
+        if let Some(index) = order.iceberg {
+            let iceberg = &mut self.icebergs[index];
+            iceberg.executed_quantity += quantity as u64;
+            iceberg.resting_quantity = iceberg.resting_quantity.saturating_sub(quantity);
+            if let Some(timestamp) = timestamp {
+                iceberg.last_seen = timestamp;
+            }
+        }
+        
+        if quantity < resting.quantity {
+            return;
+        }
+        
+        let Some(order) = self.orders.remove(&order_id) else {
+            return;
+        };
+        
+        if let Some(index) = order.iceberg {
+            let iceberg = &mut self.icebergs[index];
+            iceberg.resting_quantity = 0;
+            if iceberg.final_clip || timestamp.is_none() {
+                iceberg.state = IcebergState::Finished;
+                return;
+            }
+            iceberg.state = IcebergState::AwaitingRefill;
+        }
+        
+        let Some(filled_at) = timestamp else {
+            return;
+        };
+        
+        self.pending.push_back(PendingRefill {
+            side: resting.side,
+            price: resting.price,
+            order_id,
+            pid: resting.pid.clone(),
+            display: order.display,
+            executed: order.executed,
+            iceberg: order.iceberg,
+            filled_at,
+        });
+    }
+    
+    /// Join a new Add to the best matching fill, starting a chain if needed
+    fn link_refill(
+        &mut self,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: u32,
+        pid: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Option<usize> {
+        let (position, confidence) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, fill)| fill.side == side && fill.price == price)
+            .map(|(position, fill)| (position, self.link_confidence(fill, quantity, pid, timestamp)))
+            .filter(|&(_, confidence)| confidence >= self.config.min_link_confidence)
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        
+        let fill = self.pending.remove(position)?;
+        let index = match fill.iceberg {
+            Some(index) => index,
+            None => {
+                self.icebergs.push(Iceberg {
+                    id: self.icebergs.len(),
+                    side,
+                    price,
+                    order_ids: vec![fill.order_id],
+                    display_size: fill.display,
+                    executed_quantity: fill.executed,
+                    resting_quantity: 0,
+                    confidence: 0.0,
+                    state: IcebergState::AwaitingRefill,
+                    final_clip: false,
+                    first_seen: fill.filled_at,
+                    last_seen: fill.filled_at,
+                });
+                self.icebergs.len() - 1
+            },
+        };
+        
+        let iceberg = &mut self.icebergs[index];
+        iceberg.order_ids.push(order_id);
+        iceberg.resting_quantity = quantity;
+        iceberg.state = IcebergState::Resting;
+        iceberg.final_clip = quantity < iceberg.display_size;
+        iceberg.last_seen = timestamp;
+        // Each refill is independent evidence: the chain is wrong only if every link is
+        iceberg.confidence = 1.0 - (1.0 - iceberg.confidence) * (1.0 - confidence);
+        Some(index)
+    }
+    
+    /// Score a fill/refill pair from the gap, the refill size and the participant
+    fn link_confidence(&self, fill: &PendingRefill, quantity: u32, pid: &str, timestamp: DateTime<Utc>) -> f64 {
+        let Ok(gap) = (timestamp - fill.filled_at).to_std() else {
+            return 0.0;
+        };
+        if gap > self.config.refill_window {
+            return 0.0;
+        }
+        
+        let window = self.config.refill_window.as_secs_f64();
+        let timing = if window > 0.0 { 1.0 - gap.as_secs_f64() / window } else { 1.0 };
+        
+        // A smaller refill is the remainder of the reserve; a larger one is unusual
+        let size = match quantity.cmp(&fill.display) {
+            std::cmp::Ordering::Equal => 1.0,
+            std::cmp::Ordering::Less => 0.75,
+            std::cmp::Ordering::Greater => 0.25,
+        };
+        
+        // Participant IDs are often blank or anonymised, which says nothing either way
+        let participant = match (pid.trim(), fill.pid.trim()) {
+            ("", _) | (_, "") => 0.75,
+            (new, old) if new == old => 1.0,
+            _ => 0.25,
+        };
+        
+        timing * size * participant
+    }
+    
+    /// Drop fills whose refill window has passed, finishing their icebergs
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let window = self.config.refill_window;
+        let icebergs = &mut self.icebergs;
+        
+        // Scan every fill: arrival order need not match fill time across messages
+        self.pending.retain(|fill| {
+            let expired = (now - fill.filled_at).to_std().is_ok_and(|gap| gap > window);
+            if expired {
+                if let Some(index) = fill.iceberg {
+                    icebergs[index].state = IcebergState::Finished;
+                }
+            }
+            !expired
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    
+    fn ms(millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + millis * 1_000_000)
+    }
+    
+    fn add(order_id: u64, quantity: u32, price: u64, millis: i64) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: ms(millis),
+            order_id: OrderId(order_id),
+            side: Side::Sell,
+            quantity,
+            symbol: "AAPL".to_string(),
+            price: Price(price),
+            pid: "ICE".to_string(),
+        }
+    }
+    
+    fn execute(order_id: u64, quantity: u32, millis: i64) -> PitchMessage {
+        PitchMessage::OrderExecuted {
+            timestamp: ms(millis),
+            order_id: OrderId(order_id),
+            executed_quantity: quantity,
+            execution_id: ExecutionId(order_id),
+            contra_order_id: OrderId(0),
+            contra_pid: String::new(),
+        }
+    }
+    
+    fn run(messages: &[PitchMessage]) -> (IcebergAnalyzer, OrderBook) {
+        let mut analyzer = IcebergAnalyzer::new(IcebergConfig::default());
+        let mut book = OrderBook::new("AAPL".to_string());
+        for message in messages {
+            analyzer.apply(&mut book, message).unwrap();
+        }
+        (analyzer, book)
+    }
+    
+    #[test]
+    fn full_fills_followed_by_same_price_adds_form_a_chain() {
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            execute(1, 100, 1),
+            add(2, 100, 1_000, 2),
+            execute(2, 30, 3),
+        ]);
+        
+        let iceberg = analyzer.iceberg_for_order(OrderId(2)).unwrap();
+        assert_eq!(iceberg.order_ids, vec![OrderId(1), OrderId(2)]);
+        assert_eq!(iceberg.display_size, 100);
+        assert_eq!(iceberg.executed_quantity, 130);
+        assert_eq!(iceberg.resting_quantity, 70);
+        assert_eq!(iceberg.state, IcebergState::Resting);
+        assert_eq!(iceberg.estimated_total_size(), 300);
+        
+        let one_link = iceberg.confidence;
+        assert!(one_link > 0.7, "{}", one_link);
+        
+        // Another prompt refill only strengthens the chain
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            execute(1, 100, 1),
+            add(2, 100, 1_000, 2),
+            execute(2, 100, 3),
+            add(3, 100, 1_000, 4),
+        ]);
+        let iceberg = analyzer.iceberg_for_order(OrderId(3)).unwrap();
+        assert_eq!(iceberg.refills(), 2);
+        assert!(iceberg.confidence > one_link);
+        assert_eq!(analyzer.reserve_at(Side::Sell, Price(1_000)).unwrap().suspected_reserve, 100);
+    }
+    
+    #[test]
+    fn smaller_refill_is_the_last_clip() {
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            execute(1, 100, 1),
+            add(2, 40, 1_000, 2),
+        ]);
+        
+        let iceberg = analyzer.iceberg_for_order(OrderId(2)).unwrap();
+        assert!(iceberg.final_clip);
+        assert_eq!(iceberg.suspected_reserve(), 0);
+    }
+    
+    #[test]
+    fn late_or_elsewhere_adds_are_not_refills() {
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            execute(1, 100, 1),
+            add(2, 100, 1_010, 2),  // Different price
+            add(3, 100, 1_000, 10), // Outside the 5ms window
+        ]);
+        
+        assert!(analyzer.icebergs().is_empty());
+    }
+    
+    #[test]
+    fn fills_expire_by_exchange_time_only() {
+        let local_clock = PitchMessage::OrderExecutedAtPrice {
+            timestamp: ms(60_000), // Decoded with the local clock, far ahead of the feed
+            order_id: OrderId(9),
+            executed_quantity: 1,
+            execution_id: ExecutionId(9),
+            contra_order_id: OrderId(0),
+            contra_pid: String::new(),
+            execution_type: ' ',
+            price: Price(0),
+        };
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            add(9, 100, 1_050, 0),
+            execute(1, 100, 1),
+            local_clock,
+            add(2, 100, 1_000, 2),
+        ]);
+        
+        assert_eq!(analyzer.icebergs().len(), 1);
+    }
+    
+    #[test]
+    fn expiry_does_not_stop_at_a_newer_fill() {
+        let (analyzer, _) = run(&[
+            add(1, 100, 990, 0),
+            add(2, 100, 1_000, 0),
+            execute(2, 100, 0),
+            add(3, 100, 1_000, 0),
+            // Fills arrive out of time order, leaving the newer one first in the queue
+            execute(1, 100, 20),
+            execute(3, 100, 1),
+            add(4, 100, 1_020, 10),
+        ]);
+        
+        // Order 3's fill is 9ms old at 10ms, so its iceberg finished even though order 1's is not
+        assert_eq!(analyzer.icebergs()[0].order_ids, vec![OrderId(2), OrderId(3)]);
+        assert_eq!(analyzer.icebergs()[0].state, IcebergState::Finished);
+    }
+    
+    #[test]
+    fn fill_without_exchange_time_ends_the_chain() {
+        let execute_at_price = PitchMessage::OrderExecutedAtPrice {
+            timestamp: ms(3),
+            order_id: OrderId(2),
+            executed_quantity: 100,
+            execution_id: ExecutionId(2),
+            contra_order_id: OrderId(0),
+            contra_pid: String::new(),
+            execution_type: ' ',
+            price: Price(1_000),
+        };
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            execute(1, 100, 1),
+            add(2, 100, 1_000, 2),
+            execute_at_price,
+            add(3, 100, 1_000, 4),
+        ]);
+        
+        assert_eq!(analyzer.icebergs().len(), 1);
+        assert_eq!(analyzer.icebergs()[0].state, IcebergState::Finished);
+        assert!(analyzer.iceberg_for_order(OrderId(3)).is_none());
+    }
+    
+    #[test]
+    fn modify_updates_the_display_size() {
+        let (analyzer, _) = run(&[
+            add(1, 100, 1_000, 0),
+            PitchMessage::ModifyOrder {
+                timestamp: ms(1),
+                order_id: OrderId(1),
+                quantity: 60,
+                price: Price(1_000),
+            },
+            execute(1, 60, 2),
+            add(2, 60, 1_000, 3),
+        ]);
+        
+        let iceberg = analyzer.iceberg_for_order(OrderId(2)).unwrap();
+        assert_eq!(iceberg.display_size, 60);
+        assert!(!iceberg.final_clip);
+        assert_eq!(iceberg.suspected_reserve(), 60);
+    }
+}
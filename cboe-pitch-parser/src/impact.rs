@@ -0,0 +1,231 @@
+use crate::{message::*, order_book::OrderBook};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of a hypothetical marketable order walking the opposite side of the book.
+///
+/// Prices stay in raw `Price` units (7 implied decimals); `notional` is the exact sum of
+/// raw price × shares over every fill.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepEstimate {
+    pub side: Side, // Side of the hypothetical order; a buy consumes asks
+    pub requested: u64,
+    pub filled: u64,
+    pub unfilled: u64,
+    pub notional: u128,
+    pub best_price: Option<Price>,  // First level touched
+    pub worst_price: Option<Price>, // Last level touched
+    pub levels_consumed: usize,     // Including a partially consumed last level
+    pub mid: Option<Price>,         // Rounded down to the nearest raw unit
+    mid_sum: Option<u64>,           // Bid plus ask, so slippage uses the exact mid
+}
+
+impl SweepEstimate {
+    /// Volume-weighted fill price, rounded to the nearest raw unit
+    pub fn average_price(&self) -> Option<Price> {
+        (self.filled > 0).then(|| {
+            let filled = self.filled as u128;
+            Price(((self.notional + filled / 2) / filled) as u64)
+        })
+    }
+    
+    /// Cost against the mid in raw price units × shares, doubled to stay exact when the
+    /// mid falls between raw units; positive when the order pays away from the mid
+    pub fn slippage_cost_x2(&self) -> Option<i128> {
+        let mid_sum = self.mid_sum? as i128;
+        let cost = 2 * self.notional as i128 - mid_sum * self.filled as i128;
+        Some(match self.side {
+            Side::Buy => cost,
+            Side::Sell => -cost,
+        })
+    }
+    
+    /// Average slippage versus the mid in basis points
+    pub fn slippage_bps(&self) -> Option<f64> {
+        let reference = self.mid_sum? as i128 * self.filled as i128;
+        (reference > 0).then(|| self.slippage_cost_x2().unwrap_or(0) as f64 * 10_000.0 / reference as f64)
+    }
+    
+    pub fn is_complete(&self) -> bool {
+        self.unfilled == 0
+    }
+}
+
+impl OrderBook {
+    /// Walk the levels a marketable `side` order of `quantity` shares would consume right now
+    pub fn sweep(&self, side: Side, quantity: u64) -> SweepEstimate {
+        let mut estimate = SweepEstimate {
+            side,
+            requested: quantity,
+            filled: 0,
+            unfilled: quantity,
+            notional: 0,
+            best_price: None,
+            worst_price: None,
+            levels_consumed: 0,
+            mid: self.mid_sum().map(|sum| Price(sum / 2)),
+            mid_sum: self.mid_sum(),
+        };
+        
+        for level in self.levels(side.opposite()) {
+            if estimate.unfilled == 0 {
+                break;
+            }
+            
+            let take = estimate.unfilled.min(level.quantity());
+            estimate.filled += take;
+            estimate.unfilled -= take;
+            estimate.notional += level.price().0 as u128 * take as u128;
+            estimate.best_price.get_or_insert(level.price());
+            estimate.worst_price = Some(level.price());
+            estimate.levels_consumed += 1;
+        }
+        
+        estimate
+    }
+    
+    /// Shares a marketable `side` order could take within `bps` basis points of the mid,
+    /// or `None` without both a bid and an ask
+    pub fn liquidity_within_bps(&self, side: Side, bps: u32) -> Option<u64> {
+        let mid_sum = self.mid_sum()? as u128;
+        
+        // price ≤ mid × (1 + bps/10⁴) with both sides scaled to integers
+        let limit = match side {
+            Side::Buy => mid_sum * (10_000 + bps as u128),
+            Side::Sell => mid_sum * 10_000u128.saturating_sub(bps as u128),
+        };
+        let within = |price: Price| {
+            let scaled = price.0 as u128 * 20_000;
+            match side {
+                Side::Buy => scaled <= limit,
+                Side::Sell => scaled >= limit,
+            }
+        };
+        
+        Some(
+            self.levels(side.opposite())
+                .take_while(|level| within(level.price()))
+                .map(|level| level.quantity())
+                .sum(),
+        )
+    }
+    
+    /// Best bid plus best ask, twice the mid without rounding
+    fn mid_sum(&self) -> Option<u64> {
+        let bid = self.levels(Side::Buy).next()?.price().0;
+        let ask = self.levels(Side::Sell).next()?.price().0;
+        Some(bid + ask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    
+    /// Bids 100 @ 9.99 and 50 @ 9.98; asks 100 @ 10.00, 200 @ 10.01 and 300 @ 10.05
+    fn book() -> OrderBook {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let orders = [
+            (Side::Buy, 100, 99_900_000),
+            (Side::Buy, 50, 99_800_000),
+            (Side::Sell, 100, 100_000_000),
+            (Side::Sell, 200, 100_100_000),
+            (Side::Sell, 300, 100_500_000),
+        ];
+        
+        for (id, (side, quantity, price)) in orders.into_iter().enumerate() {
+            book.apply_message(&PitchMessage::AddOrder {
+                timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000),
+                order_id: OrderId(id as u64 + 1),
+                side,
+                quantity,
+                symbol: "AAPL".to_string(),
+                price: Price(price),
+                pid: "TEST".to_string(),
+            })
+            .unwrap();
+        }
+        book
+    }
+    
+    #[test]
+    fn buy_sweep_walks_asks_best_first() {
+        let estimate = book().sweep(Side::Buy, 250);
+        
+        assert_eq!((estimate.filled, estimate.unfilled), (250, 0));
+        assert!(estimate.is_complete());
+        assert_eq!(estimate.levels_consumed, 2);
+        assert_eq!(estimate.best_price, Some(Price(100_000_000)));
+        assert_eq!(estimate.worst_price, Some(Price(100_100_000)));
+        assert_eq!(estimate.notional, 100 * 100_000_000 + 150 * 100_100_000);
+        assert_eq!(estimate.average_price(), Some(Price(100_060_000)));
+        assert_eq!(estimate.mid, Some(Price(99_950_000)));
+    }
+    
+    #[test]
+    fn slippage_is_exact_against_half_unit_mid() {
+        let estimate = book().sweep(Side::Buy, 250);
+        
+        // 2 × notional − (bid + ask) × filled
+        assert_eq!(estimate.slippage_cost_x2(), Some(55_000_000));
+        let bps = estimate.slippage_bps().unwrap();
+        assert!((bps - 55_000_000.0 * 10_000.0 / (199_900_000.0 * 250.0)).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn sell_sweep_pays_away_from_the_mid() {
+        let estimate = book().sweep(Side::Sell, 120);
+        
+        assert_eq!(estimate.levels_consumed, 2);
+        assert_eq!(estimate.worst_price, Some(Price(99_800_000)));
+        assert_eq!(estimate.slippage_cost_x2(), Some(16_000_000));
+        assert!(estimate.slippage_bps().unwrap() > 0.0);
+    }
+    
+    #[test]
+    fn sweep_beyond_the_book_is_partial() {
+        let estimate = book().sweep(Side::Buy, 1_000);
+        
+        assert_eq!((estimate.filled, estimate.unfilled), (600, 400));
+        assert!(!estimate.is_complete());
+        assert_eq!(estimate.levels_consumed, 3);
+    }
+    
+    #[test]
+    fn sweep_of_empty_book_fills_nothing() {
+        let estimate = OrderBook::new("AAPL".to_string()).sweep(Side::Buy, 10);
+        
+        assert_eq!(estimate.filled, 0);
+        assert_eq!(estimate.average_price(), None);
+        assert_eq!(estimate.slippage_cost_x2(), None);
+        assert_eq!(estimate.slippage_bps(), None);
+    }
+    
+    #[test]
+    fn liquidity_within_bps_counts_levels_inside_the_band() {
+        let book = book();
+        
+        // Mid is 9.995
+        assert_eq!(book.liquidity_within_bps(Side::Buy, 0), Some(0));
+        assert_eq!(book.liquidity_within_bps(Side::Buy, 10), Some(100));
+        assert_eq!(book.liquidity_within_bps(Side::Buy, 16), Some(300));
+        assert_eq!(book.liquidity_within_bps(Side::Buy, 100), Some(600));
+        assert_eq!(book.liquidity_within_bps(Side::Sell, 10), Some(100));
+        assert_eq!(book.liquidity_within_bps(Side::Sell, 20), Some(150));
+        assert_eq!(book.liquidity_within_bps(Side::Sell, 20_000), Some(150));
+    }
+    
+    #[test]
+    fn liquidity_within_bps_needs_both_sides() {
+        let mut book = book();
+        for id in [1, 2] {
+            book.apply_message(&PitchMessage::DeleteOrder {
+                timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000),
+                order_id: OrderId(id),
+            })
+            .unwrap();
+        }
+        
+        assert_eq!(book.liquidity_within_bps(Side::Buy, 100), None);
+    }
+}
@@ -2,10 +2,46 @@ pub mod message;
 pub mod parser;
 pub mod simulator;
 pub mod order_book;
+pub mod book_manager;
+pub mod bbo;
+pub mod checkpoint;
+pub mod diagnostics;
+pub mod reconcile;
+pub mod iceberg;
+pub mod status;
+pub mod history;
+pub mod snapshot;
+pub mod impact;
+pub mod metrics;
 pub mod error;
+pub mod receiver;
+pub mod arbitration;
+pub mod health;
+pub mod pcap;
+pub mod capture;
+pub mod replay;
+pub mod merge;
 
 pub use message::*;
 pub use parser::*;
 pub use simulator::*;
 pub use order_book::*;
+pub use book_manager::*;
+pub use bbo::*;
+pub use checkpoint::*;
+pub use diagnostics::*;
+pub use reconcile::*;
+pub use iceberg::*;
+pub use status::*;
+pub use history::*;
+pub use snapshot::*;
+pub use impact::*;
+pub use metrics::*;
 pub use error::*;
+pub use receiver::*;
+pub use arbitration::*;
+pub use health::*;
+pub use pcap::*;
+pub use capture::*;
+pub use replay::*;
+pub use merge::*;
@@ -42,7 +42,9 @@ fn main() -> Result<()> {
             println!("     -> Type: 0x{:02X}, Time: {}", 
                     message.message_type(), message.timestamp());
             
-            order_book.apply_message(message);
+            if let Err(e) = order_book.apply_message(message) {
+                println!("     ⚠️  {}", e);
+            }
         }
     }
     
@@ -0,0 +1,244 @@
+use crate::{error::*, message::*, parser::PitchParser, replay::FrameSource};
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// A message from the merged stream, tagged with where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedMessage {
+    pub unit: u8,
+    pub sequence: u32,
+    pub timestamp: DateTime<Utc>, // Exchange time used for ordering
+    pub message: PitchMessage,
+}
+
+struct UnitCursor {
+    source: Box<dyn FrameSource>,
+    pending: VecDeque<MergedMessage>,
+    last_time: Option<DateTime<Utc>>,
+}
+
+impl UnitCursor {
+    fn next_message(&mut self, parser: &PitchParser) -> Result<Option<MergedMessage>> {
+        while self.pending.is_empty() {
+            let Some(frame) = self.source.next_frame()? else {
+                return Ok(None);
+            };
+            
+            let messages = frame.parse(parser)?;
+            
+            // Messages without an exchange time take the unit's current time,
+            // or the frame's first stamped message at the start
+            let mut time = self.last_time
+                .or_else(|| messages.iter().find_map(PitchMessage::exchange_timestamp))
+                .unwrap_or(frame.received_at);
+            
+            for (i, message) in messages.into_iter().enumerate() {
+                if let Some(timestamp) = message.exchange_timestamp() {
+                    time = timestamp;
+                }
+                
+                self.pending.push_back(MergedMessage {
+                    unit: frame.header.unit,
+                    sequence: frame.header.sequence.wrapping_add(i as u32),
+                    timestamp: time,
+                    message,
+                });
+            }
+            
+            self.last_time = Some(time);
+        }
+        
+        Ok(self.pending.pop_front())
+    }
+}
+
+// (exchange time, unit, sequence, cursor index)
+type HeapEntry = Reverse<(DateTime<Utc>, u8, u32, usize)>;
+
+/// K-way merge of per-unit frame sources into one exchange-time ordered stream
+///
+/// Ties are broken by unit, then sequence, so the output is deterministic.
+/// Each unit's own messages always stay in sequence order.
+pub struct UnitMerger {
+    cursors: Vec<UnitCursor>,
+    heads: Vec<Option<MergedMessage>>,
+    heap: BinaryHeap<HeapEntry>,
+    parser: PitchParser,
+    primed: bool,
+}
+
+impl UnitMerger {
+    pub fn new() -> Self {
+        Self {
+            cursors: Vec::new(),
+            heads: Vec::new(),
+            heap: BinaryHeap::new(),
+            parser: PitchParser::new(),
+            primed: false,
+        }
+    }
+    
+    pub fn add_source<S: FrameSource + 'static>(&mut self, source: S) {
+        self.cursors.push(UnitCursor {
+            source: Box::new(source),
+            pending: VecDeque::new(),
+            last_time: None,
+        });
+        self.heads.push(None);
+        self.primed = false;
+    }
+    
+    pub fn source_count(&self) -> usize {
+        self.cursors.len()
+    }
+    
+    pub fn next_message(&mut self) -> Result<Option<MergedMessage>> {
+        if !self.primed {
+            for index in 0..self.cursors.len() {
+                if self.heads[index].is_none() {
+                    self.refill(index)?;
+                }
+            }
+            self.primed = true;
+        }
+        
+        let Some(Reverse((_, _, _, index))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        
+        let message = self.heads[index].take();
+        self.refill(index)?;
+        Ok(message)
+    }
+    
+    fn refill(&mut self, index: usize) -> Result<()> {
+        if let Some(message) = self.cursors[index].next_message(&self.parser)? {
+            self.heap.push(Reverse((message.timestamp, message.unit, message.sequence, index)));
+            self.heads[index] = Some(message);
+        }
+        Ok(())
+    }
+}
+
+impl Default for UnitMerger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterator for UnitMerger {
+    type Item = Result<MergedMessage>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::RawFrameSource;
+    use crate::simulator::PitchSimulator;
+    use chrono::TimeZone;
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    /// One frame per entry, starting at `sequence`, with a delete at each of the given seconds
+    fn unit(unit: u8, frames: &[(u32, &[i64])]) -> RawFrameSource {
+        let simulator = PitchSimulator::new();
+        let mut data = Vec::new();
+        
+        for &(sequence, seconds) in frames {
+            let messages: Vec<PitchMessage> = seconds
+                .iter()
+                .enumerate()
+                .map(|(i, &s)| PitchMessage::DeleteOrder {
+                    timestamp: at(s),
+                    order_id: OrderId(sequence as u64 + i as u64),
+                })
+                .collect();
+            let header = SequencedUnitHeader { length: 0, count: messages.len() as u8, unit, sequence };
+            data.extend(simulator.serialize_frame(&header, &messages).unwrap());
+        }
+        
+        RawFrameSource::new(data)
+    }
+    
+    fn drain(merger: &mut UnitMerger) -> Vec<(i64, u8, u32)> {
+        merger
+            .by_ref()
+            .map(|message| {
+                let message = message.unwrap();
+                ((message.timestamp - at(0)).num_seconds(), message.unit, message.sequence)
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn units_are_interleaved_by_exchange_time() {
+        let mut merger = UnitMerger::new();
+        merger.add_source(unit(1, &[(1, &[0, 2]), (3, &[4])]));
+        merger.add_source(unit(2, &[(1, &[1]), (2, &[3, 5])]));
+        merger.add_source(unit(3, &[(7, &[2, 6])]));
+        
+        assert_eq!(drain(&mut merger), vec![
+            (0, 1, 1),
+            (1, 2, 1),
+            (2, 1, 2),
+            (2, 3, 7),
+            (3, 2, 2),
+            (4, 1, 3),
+            (5, 2, 3),
+            (6, 3, 8),
+        ]);
+    }
+    
+    #[test]
+    fn ties_break_by_unit_then_sequence() {
+        // Registration order must not matter
+        let mut merger = UnitMerger::new();
+        merger.add_source(unit(4, &[(50, &[0, 0])]));
+        merger.add_source(unit(2, &[(90, &[0]), (91, &[0])]));
+        merger.add_source(unit(3, &[(10, &[0])]));
+        
+        assert_eq!(drain(&mut merger), vec![
+            (0, 2, 90),
+            (0, 2, 91),
+            (0, 3, 10),
+            (0, 4, 50),
+            (0, 4, 51),
+        ]);
+    }
+    
+    #[test]
+    fn units_may_start_and_finish_at_different_times() {
+        let mut merger = UnitMerger::new();
+        merger.add_source(unit(1, &[(1, &[0, 1, 2])]));
+        merger.add_source(unit(2, &[(1, &[5]), (2, &[6])]));
+        merger.add_source(unit(3, &[(1, &[1, 9])]));
+        merger.add_source(unit(4, &[]));
+        
+        let merged = drain(&mut merger);
+        
+        assert_eq!(merged.len(), 7);
+        assert!(merged.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", merged);
+        assert_eq!(merged.last(), Some(&(9, 3, 2)));
+        assert!(merger.next_message().unwrap().is_none());
+    }
+    
+    #[test]
+    fn source_added_after_priming_joins_the_merge() {
+        let mut merger = UnitMerger::new();
+        merger.add_source(unit(1, &[(1, &[0, 4, 8])]));
+        
+        assert_eq!(merger.next_message().unwrap().unwrap().sequence, 1);
+        
+        merger.add_source(unit(2, &[(1, &[2, 6])]));
+        assert_eq!(merger.source_count(), 2);
+        
+        assert_eq!(drain(&mut merger), vec![(2, 2, 1), (4, 1, 2), (6, 2, 2), (8, 1, 3)]);
+    }
+}
@@ -10,6 +10,13 @@ pub struct SequencedUnitHeader {
     pub sequence: u32,   // Sequence number of first message
 }
 
+impl SequencedUnitHeader {
+    /// Zero-count frames carry no messages and only keep the unit alive
+    pub fn is_heartbeat(&self) -> bool {
+        self.count == 0
+    }
+}
+
 /// Binary price with 7 decimal places (denominator = 10,000,000)
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Price(pub u64);
@@ -89,6 +96,16 @@ pub enum TradingStatus {
 }
 
 impl TradingStatus {
+    /// States where the book may legitimately cross while orders await an auction
+    pub fn is_auction(&self) -> bool {
+        matches!(self, TradingStatus::PreOpen | TradingStatus::PreClose | TradingStatus::Halted)
+    }
+    
+    /// States with continuous matching
+    pub fn is_tradable(&self) -> bool {
+        matches!(self, TradingStatus::Trading | TradingStatus::MocTrading)
+    }
+    
     pub fn from_byte(b: u8) -> Option<Self> {
         match b {
             b'C' => Some(TradingStatus::Closed),
@@ -120,13 +137,20 @@ impl TradingStatus {
 }
 
 /// Side indicator for orders
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Side {
     Buy,   // B
     Sell,  // S
 }
 
 impl Side {
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+    
     pub fn from_byte(b: u8) -> Option<Self> {
         match b {
             b'B' => Some(Side::Buy),
@@ -282,4 +306,18 @@ impl PitchMessage {
             PitchMessage::AuctionSummary { timestamp, .. } => *timestamp,
         }
     }
+    
+    /// The exchange time of the event, or `None` for messages whose decoded timestamp
+    /// is only the local clock
+    pub fn exchange_timestamp(&self) -> Option<DateTime<Utc>> {
+        Self::has_exchange_timestamp(self.message_type()).then(|| self.timestamp())
+    }
+    
+    /// Whether messages of `message_type` are decoded with their exchange timestamp.
+    ///
+    /// Unit Clear and End of Session carry none, and the parser does not yet decode
+    /// Order Executed at Price, Calculated Value or the auction messages.
+    pub fn has_exchange_timestamp(message_type: u8) -> bool {
+        matches!(message_type, 0x37..=0x3E)
+    }
 }
@@ -0,0 +1,257 @@
+use crate::{message::*, order_book::OrderBook};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Order-book features at one instant; prices are decimal, `None` where a side is empty
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookMetrics {
+    pub mid: Option<f64>,
+    pub microprice: Option<f64>,
+    pub weighted_mid: Option<f64>, // Microprice over the top N levels
+    pub spread_bps: Option<f64>,
+    pub imbalance: Option<f64>,    // (bid depth − ask depth) / total over the top N levels
+    pub bid_slope: Option<f64>,    // Shares per unit of price away from the mid
+    pub ask_slope: Option<f64>,
+}
+
+impl BookMetrics {
+    fn values(&self) -> [Option<f64>; 7] {
+        [
+            self.mid,
+            self.microprice,
+            self.weighted_mid,
+            self.spread_bps,
+            self.imbalance,
+            self.bid_slope,
+            self.ask_slope,
+        ]
+    }
+    
+    fn from_values(values: [Option<f64>; 7]) -> Self {
+        let [mid, microprice, weighted_mid, spread_bps, imbalance, bid_slope, ask_slope] = values;
+        Self {
+            mid,
+            microprice,
+            weighted_mid,
+            spread_bps,
+            imbalance,
+            bid_slope,
+            ask_slope,
+        }
+    }
+}
+
+impl OrderBook {
+    pub fn mid_price(&self) -> Option<f64> {
+        let (bid, ask) = (self.best_bid()?, self.best_ask()?);
+        Some((bid.to_decimal() + ask.to_decimal()) / 2.0)
+    }
+    
+    /// Mid weighted towards the side with less size, where the next trade is likelier
+    pub fn microprice(&self) -> Option<f64> {
+        self.weighted_mid(1)
+    }
+    
+    /// Microprice using each side's volume-weighted price and depth over `levels` levels
+    pub fn weighted_mid(&self, levels: usize) -> Option<f64> {
+        let (bid_price, bid_depth) = self.weighted_side(Side::Buy, levels)?;
+        let (ask_price, ask_depth) = self.weighted_side(Side::Sell, levels)?;
+        Some((bid_price * ask_depth + ask_price * bid_depth) / (bid_depth + ask_depth))
+    }
+    
+    pub fn spread_bps(&self) -> Option<f64> {
+        let (bid, ask) = (self.best_bid()?, self.best_ask()?);
+        let mid = self.mid_price()?;
+        (mid > 0.0).then(|| (ask.to_decimal() - bid.to_decimal()) / mid * 10_000.0)
+    }
+    
+    /// Depth imbalance over `levels` levels, from −1 (all asks) to 1 (all bids)
+    pub fn depth_imbalance(&self, levels: usize) -> Option<f64> {
+        let bids = self.depth(Side::Buy, levels);
+        let asks = self.depth(Side::Sell, levels);
+        let total = bids + asks;
+        (total > 0).then(|| (bids as f64 - asks as f64) / total as f64)
+    }
+    
+    /// Least-squares slope through the origin of cumulative depth against distance from
+    /// the mid over `levels` levels; steeper means more size close to the touch
+    pub fn book_slope(&self, side: Side, levels: usize) -> Option<f64> {
+        let mid = self.mid_price()?;
+        let mut cumulative = 0.0;
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        
+        for level in self.levels(side).take(levels) {
+            cumulative += level.quantity() as f64;
+            let distance = (level.price().to_decimal() - mid).abs();
+            covariance += distance * cumulative;
+            variance += distance * distance;
+        }
+        
+        (variance > 0.0).then(|| covariance / variance)
+    }
+    
+    pub fn metrics(&self, levels: usize) -> BookMetrics {
+        BookMetrics {
+            mid: self.mid_price(),
+            microprice: self.microprice(),
+            weighted_mid: self.weighted_mid(levels),
+            spread_bps: self.spread_bps(),
+            imbalance: self.depth_imbalance(levels),
+            bid_slope: self.book_slope(Side::Buy, levels),
+            ask_slope: self.book_slope(Side::Sell, levels),
+        }
+    }
+    
+    fn depth(&self, side: Side, levels: usize) -> u64 {
+        self.levels(side).take(levels).map(|level| level.quantity()).sum()
+    }
+    
+    /// Volume-weighted decimal price and total depth of one side
+    fn weighted_side(&self, side: Side, levels: usize) -> Option<(f64, f64)> {
+        let (notional, depth) = self
+            .levels(side)
+            .take(levels)
+            .fold((0u128, 0u64), |(notional, depth), level| {
+                (notional + level.price().0 as u128 * level.quantity() as u128, depth + level.quantity())
+            });
+        (depth > 0).then(|| (notional as f64 / depth as f64 / 10_000_000.0, depth as f64))
+    }
+}
+
+/// Time-weighted averages of `BookMetrics` over a session.
+///
+/// Call `sample` after every message applied to the book; each value holds until the
+/// next sample, and time when a metric was undefined is left out of its average.
+#[derive(Debug, Clone, Default)]
+pub struct TimeWeightedMetrics {
+    levels: usize,
+    last: Option<(DateTime<Utc>, BookMetrics)>,
+    weighted: [f64; 7],  // Σ value × nanoseconds held
+    durations: [i64; 7], // Σ nanoseconds defined
+}
+
+impl TimeWeightedMetrics {
+    pub fn new(levels: usize) -> Self {
+        Self {
+            levels,
+            ..Self::default()
+        }
+    }
+    
+    /// Sample `book` after it applied `message`, at the message's exchange time.
+    ///
+    /// Messages without one take the previous sample's time, and are skipped before
+    /// the first timestamped message.
+    pub fn sample(&mut self, book: &OrderBook, message: &PitchMessage) {
+        let time = message.exchange_timestamp().or(self.last.map(|(time, _)| time));
+        if let Some(time) = time {
+            self.sample_at(time, book.metrics(self.levels));
+        }
+    }
+    
+    pub fn sample_at(&mut self, time: DateTime<Utc>, metrics: BookMetrics) {
+        self.accumulate(time);
+        self.last = Some((time, metrics));
+    }
+    
+    /// Averages so far, with the latest sample held until `until`
+    pub fn averages(&self, until: DateTime<Utc>) -> BookMetrics {
+        let mut averages = self.clone();
+        averages.accumulate(until);
+        
+        let mut values = [None; 7];
+        for (value, (&weighted, &duration)) in values.iter_mut().zip(averages.weighted.iter().zip(&averages.durations)) {
+            *value = (duration > 0).then(|| weighted / duration as f64);
+        }
+        BookMetrics::from_values(values)
+    }
+    
+    /// The most recent sample
+    pub fn latest(&self) -> Option<&BookMetrics> {
+        self.last.as_ref().map(|(_, metrics)| metrics)
+    }
+    
+    /// Credit the previous sample with the time it was in force
+    fn accumulate(&mut self, time: DateTime<Utc>) {
+        let Some((since, metrics)) = &self.last else {
+            return;
+        };
+        let Some(held) = (time - *since).num_nanoseconds().filter(|&nanos| nanos > 0) else {
+            return;
+        };
+        
+        for (index, value) in metrics.values().into_iter().enumerate() {
+            if let Some(value) = value {
+                self.weighted[index] += value * held as f64;
+                self.durations[index] += held;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn add(order_id: u64, side: Side, price: u64, seconds: i64) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: at(seconds),
+            order_id: OrderId(order_id),
+            side,
+            quantity: 100,
+            symbol: "AAPL".to_string(),
+            price: Price(price),
+            pid: "TEST".to_string(),
+        }
+    }
+    
+    fn apply(book: &mut OrderBook, metrics: &mut TimeWeightedMetrics, message: PitchMessage) {
+        let _ = book.apply_message(&message);
+        metrics.sample(book, &message);
+    }
+    
+    #[test]
+    fn averages_weight_each_mid_by_time_held() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let mut metrics = TimeWeightedMetrics::new(1);
+        
+        apply(&mut book, &mut metrics, add(1, Side::Buy, 99_000_000, 0));
+        apply(&mut book, &mut metrics, add(2, Side::Sell, 101_000_000, 0)); // Mid 10.00
+        apply(&mut book, &mut metrics, add(3, Side::Buy, 100_000_000, 10)); // Mid 10.05
+        
+        let mid = metrics.averages(at(40)).mid.unwrap();
+        assert!((mid - (10.00 * 10.0 + 10.05 * 30.0) / 40.0).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn locally_stamped_messages_do_not_move_the_clock() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let mut metrics = TimeWeightedMetrics::new(1);
+        
+        apply(&mut book, &mut metrics, add(1, Side::Buy, 99_000_000, 0));
+        apply(&mut book, &mut metrics, add(2, Side::Sell, 101_000_000, 0));
+        let update = book.last_update();
+        
+        // Order Executed at Price is decoded with the local clock
+        apply(&mut book, &mut metrics, PitchMessage::OrderExecutedAtPrice {
+            timestamp: Utc::now(),
+            order_id: OrderId(1),
+            executed_quantity: 50,
+            execution_id: ExecutionId(1),
+            contra_order_id: OrderId(0),
+            contra_pid: String::new(),
+            execution_type: 'O',
+            price: Price(99_000_000),
+        });
+        assert_eq!(book.last_update(), update);
+        
+        apply(&mut book, &mut metrics, add(3, Side::Buy, 100_000_000, 10));
+        let mid = metrics.averages(at(20)).mid.unwrap();
+        assert!((mid - (10.00 * 10.0 + 10.05 * 10.0) / 20.0).abs() < 1e-9);
+    }
+}
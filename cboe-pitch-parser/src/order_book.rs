@@ -1,5 +1,6 @@
-use crate::message::*;
-use std::collections::{BTreeMap, HashMap};
+use crate::{diagnostics::DiagnosticIssue, error::*, message::*};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -9,16 +10,324 @@ pub struct OrderBookEntry {
     pub quantity: u32,
     pub side: Side,
     pub pid: String,
-    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub timestamp: DateTime<Utc>,
 }
 
+/// An undisclosed order: it rests at a price but shows no quantity, and its
+/// executions are reported through Trade messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HiddenOrder {
+    pub order_id: OrderId,
+    pub side: Side,
+    pub price: Price,
+    pub pid: String,
+    pub timestamp: DateTime<Utc>,
+    pub executed_quantity: u64,
+    pub executions: u32,
+}
+
+/// Execution volume split by whether the resting order was visible
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionStats {
+    pub displayed_volume: u64,
+    pub displayed_executions: u64,
+    pub hidden_volume: u64,
+    pub hidden_executions: u64,
+    pub unattributed_volume: u64, // Trades matching no known undisclosed order
+    pub unattributed_trades: u64,
+}
+
+impl ExecutionStats {
+    /// Share of attributed volume that executed against undisclosed orders
+    pub fn hidden_ratio(&self) -> Option<f64> {
+        let total = self.displayed_volume + self.hidden_volume;
+        (total > 0).then(|| self.hidden_volume as f64 / total as f64)
+    }
+}
+
+/// Whether a book's contents can be shown to consumers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BookState {
+    #[default]
+    Live,
+    Cleared,    // Purged by Unit Clear; the unit resends its orders next
+    Recovering, // Updates may have been missed; unreliable until the next Unit Clear
+    Final,      // End of Session received
+}
+
+/// Aggregated `(price, quantity)` per level, best price first
+pub type LevelInfo = Vec<(Price, u64)>;
+
+/// A resting order in the slab, linked into its level's FIFO
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OrderNode {
+    entry: OrderBookEntry,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Slab of order nodes; vacated slots are reused so indices stay stable
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OrderSlab {
+    slots: Vec<Option<OrderNode>>,
+    free: Vec<usize>,
+}
+
+impl OrderSlab {
+    fn insert(&mut self, node: OrderNode) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(node);
+                slot
+            },
+            None => {
+                self.slots.push(Some(node));
+                self.slots.len() - 1
+            },
+        }
+    }
+    
+    fn remove(&mut self, slot: usize) -> Option<OrderNode> {
+        let node = self.slots.get_mut(slot)?.take()?;
+        self.free.push(slot);
+        Some(node)
+    }
+    
+    fn get(&self, slot: usize) -> Option<&OrderNode> {
+        self.slots.get(slot)?.as_ref()
+    }
+    
+    fn get_mut(&mut self, slot: usize) -> Option<&mut OrderNode> {
+        self.slots.get_mut(slot)?.as_mut()
+    }
+    
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.free.clear();
+    }
+}
+
+/// FIFO of the orders at one price, oldest first, with cached aggregates
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Level {
+    head: Option<usize>,
+    tail: Option<usize>,
+    quantity: u64,
+    count: usize,
+}
+
+/// Bids sort highest price first, so ascending keys are always best-first
+fn priority_key(side: Side, price: u64) -> u64 {
+    match side {
+        Side::Buy => u64::MAX - price,
+        Side::Sell => price,
+    }
+}
+
+/// Price levels for one side of the book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BookSide {
+    side: Side,
+    levels: HashMap<u64, Level>,
+    priority: BTreeSet<u64>, // Priority keys of the levels, best first
+    best: Option<u64>,       // Cached best price
+    quantity: u64,
+}
+
+impl BookSide {
+    fn new(side: Side) -> Self {
+        Self {
+            side,
+            levels: HashMap::new(),
+            priority: BTreeSet::new(),
+            best: None,
+            quantity: 0,
+        }
+    }
+    
+    /// Levels best price first
+    fn iter(&self) -> impl Iterator<Item = (u64, &Level)> {
+        self.priority.iter().filter_map(move |&key| {
+            let price = priority_key(self.side, key);
+            self.levels.get(&price).map(|level| (price, level))
+        })
+    }
+    
+    /// Link a stored order at the back of its level's queue
+    fn push_back(&mut self, slab: &mut OrderSlab, slot: usize) {
+        let Some(node) = slab.get(slot) else {
+            return;
+        };
+        let (price, quantity) = (node.entry.price.0, node.entry.quantity as u64);
+        
+        if !self.levels.contains_key(&price) {
+            let key = priority_key(self.side, price);
+            self.priority.insert(key);
+            if self.best.is_none_or(|best| key < priority_key(self.side, best)) {
+                self.best = Some(price);
+            }
+        }
+        
+        let level = self.levels.entry(price).or_default();
+        let tail = level.tail.replace(slot);
+        level.head.get_or_insert(slot);
+        level.quantity += quantity;
+        level.count += 1;
+        self.quantity += quantity;
+        
+        if let Some(node) = tail.and_then(|tail| slab.get_mut(tail)) {
+            node.next = Some(slot);
+        }
+        if let Some(node) = slab.get_mut(slot) {
+            node.prev = tail;
+            node.next = None;
+        }
+    }
+    
+    /// Take an order out of the slab and its level's queue
+    fn unlink(&mut self, slab: &mut OrderSlab, slot: usize) -> Option<OrderBookEntry> {
+        let node = slab.remove(slot)?;
+        let price = node.entry.price.0;
+        let level = self.levels.get_mut(&price)?;
+        
+        match node.prev.and_then(|prev| slab.get_mut(prev)) {
+            Some(prev) => prev.next = node.next,
+            None => level.head = node.next,
+        }
+        match node.next.and_then(|next| slab.get_mut(next)) {
+            Some(next) => next.prev = node.prev,
+            None => level.tail = node.prev,
+        }
+        
+        level.quantity -= node.entry.quantity as u64;
+        level.count -= 1;
+        self.quantity -= node.entry.quantity as u64;
+        
+        if level.count == 0 {
+            self.levels.remove(&price);
+            self.priority.remove(&priority_key(self.side, price));
+            if self.best == Some(price) {
+                self.best = self.priority.first().map(|&key| priority_key(self.side, key));
+            }
+        }
+        
+        Some(node.entry)
+    }
+    
+    fn reduce(&mut self, price: u64, quantity: u32) {
+        if let Some(level) = self.levels.get_mut(&price) {
+            level.quantity -= quantity as u64;
+        }
+        self.quantity -= quantity as u64;
+    }
+    
+    fn clear(&mut self) {
+        self.levels.clear();
+        self.priority.clear();
+        self.best = None;
+        self.quantity = 0;
+    }
+}
+
+/// One price level, with its orders in time priority
+#[derive(Debug, Clone, Copy)]
+pub struct PriceLevel<'a> {
+    side: Side,
+    price: Price,
+    level: &'a Level,
+    slab: &'a OrderSlab,
+}
+
+impl<'a> PriceLevel<'a> {
+    pub fn side(&self) -> Side {
+        self.side
+    }
+    
+    pub fn price(&self) -> Price {
+        self.price
+    }
+    
+    pub fn quantity(&self) -> u64 {
+        self.level.quantity
+    }
+    
+    pub fn order_count(&self) -> usize {
+        self.level.count
+    }
+    
+    /// Orders oldest first
+    pub fn orders(&self) -> LevelOrders<'a> {
+        LevelOrders {
+            slab: self.slab,
+            next: self.level.head,
+        }
+    }
+}
+
+/// Walks a level's queue front to back
+#[derive(Debug, Clone)]
+pub struct LevelOrders<'a> {
+    slab: &'a OrderSlab,
+    next: Option<usize>,
+}
+
+impl<'a> Iterator for LevelOrders<'a> {
+    type Item = &'a OrderBookEntry;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.slab.get(self.next?)?;
+        self.next = node.next;
+        Some(&node.entry)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LevelAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Market-by-price change to one level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LevelUpdate {
+    pub side: Side,
+    pub price: Price,
+    pub quantity: u64,      // New aggregate quantity, 0 when deleted
+    pub order_count: usize, // New order count, 0 when deleted
+    pub level_index: usize, // 0 is the best level
+    pub action: LevelAction,
+}
+
+/// Where an order sits in its level's queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuePosition {
+    pub orders_ahead: usize,
+    pub shares_ahead: u64,
+    pub level_order_count: usize,
+    pub level_quantity: u64,
+}
+
+/// Price-time priority book for one symbol
+///
+/// Orders live in a slab and are chained per price level, so add, cancel,
+/// execute and top-of-book are all O(1) apart from creating or emptying a level.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderBook {
     symbol: String,
-    orders: HashMap<OrderId, OrderBookEntry>,
-    bids: BTreeMap<u64, Vec<OrderId>>,
-    asks: BTreeMap<u64, Vec<OrderId>>,
+    orders: HashMap<OrderId, usize>, // Order -> slab slot
+    slab: OrderSlab,
+    bids: BookSide,
+    asks: BookSide,
     trading_status: TradingStatus,
+    state: BookState,
+    last_update: Option<DateTime<Utc>>,
+    hidden: HashMap<OrderId, HiddenOrder>, // Undisclosed orders, kept out of the levels
+    execution_stats: ExecutionStats,
+    #[serde(skip)]
+    track_levels: bool,
+    #[serde(skip)]
+    touched: HashMap<(Side, u64), Option<(u64, usize)>>, // Level -> (quantity, count) before the batch
 }
 
 impl OrderBook {
@@ -26,90 +335,337 @@ impl OrderBook {
         Self {
             symbol,
             orders: HashMap::new(),
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
+            slab: OrderSlab::default(),
+            bids: BookSide::new(Side::Buy),
+            asks: BookSide::new(Side::Sell),
             trading_status: TradingStatus::Closed,
+            state: BookState::Live,
+            last_update: None,
+            hidden: HashMap::new(),
+            execution_stats: ExecutionStats::default(),
+            track_levels: false,
+            touched: HashMap::new(),
         }
     }
     
-    pub fn apply_message(&mut self, message: &PitchMessage) {
+    /// Apply one message; updates to orders this book does not hold, or that take
+    /// more than the resting quantity, are reported as errors
+    pub fn apply_message(&mut self, message: &PitchMessage) -> Result<()> {
         match message {
-            PitchMessage::TradingStatus { symbol, trading_status, .. } => {
-                if symbol == &self.symbol {
-                    self.trading_status = *trading_status;
+            PitchMessage::UnitClear { .. } => {
+                self.clear();
+            },
+            PitchMessage::EndOfSession { .. } => {
+                self.state = BookState::Final;
+            },
+            PitchMessage::TradingStatus { symbol, trading_status, timestamp, .. } if symbol == &self.symbol => {
+                self.trading_status = *trading_status;
+                self.reopen();
+                self.last_update = Some(*timestamp);
+            },
+            PitchMessage::AddOrder { order_id, side, quantity, symbol, price, pid, timestamp }
+                if symbol == &self.symbol && *quantity > 0 =>
+            {
+                self.reopen();
+                self.insert_order(OrderBookEntry {
+                    order_id: *order_id,
+                    price: *price,
+                    quantity: *quantity,
+                    side: *side,
+                    pid: pid.clone(),
+                    timestamp: *timestamp,
+                });
+                self.last_update = Some(*timestamp);
+            },
+            PitchMessage::AddOrder { order_id, side, symbol, price, pid, timestamp, .. } if symbol == &self.symbol => {
+                self.reopen();
+                self.hidden.insert(*order_id, HiddenOrder {
+                    order_id: *order_id,
+                    side: *side,
+                    price: *price,
+                    pid: pid.clone(),
+                    timestamp: *timestamp,
+                    executed_quantity: 0,
+                    executions: 0,
+                });
+            },
+            PitchMessage::Trade { symbol, quantity, order_id, contra_order_id, .. } if symbol == &self.symbol => {
+                // Either side of the print may be the undisclosed order
+                let hidden_id = [*order_id, *contra_order_id].into_iter().find(|id| self.hidden.contains_key(id));
+                
+                match hidden_id.and_then(|id| self.hidden.get_mut(&id)) {
+                    Some(order) => {
+                        order.executed_quantity += *quantity as u64;
+                        order.executions += 1;
+                        self.execution_stats.hidden_volume += *quantity as u64;
+                        self.execution_stats.hidden_executions += 1;
+                    },
+                    None => {
+                        self.execution_stats.unattributed_volume += *quantity as u64;
+                        self.execution_stats.unattributed_trades += 1;
+                    },
                 }
             },
-            PitchMessage::AddOrder { order_id, side, quantity, symbol, price, pid, timestamp } => {
-                if symbol == &self.symbol && *quantity > 0 {
-                    let entry = OrderBookEntry {
-                        order_id: *order_id,
-                        price: *price,
-                        quantity: *quantity,
-                        side: *side,
-                        pid: pid.clone(),
-                        timestamp: *timestamp,
-                    };
-                    
-                    self.orders.insert(*order_id, entry);
-                    
-                    match side {
-                        Side::Buy => {
-                            self.bids.entry(price.0).or_default().push(*order_id);
-                        },
-                        Side::Sell => {
-                            self.asks.entry(price.0).or_default().push(*order_id);
-                        }
-                    }
+            PitchMessage::OrderExecuted { order_id, executed_quantity, .. }
+            | PitchMessage::OrderExecutedAtPrice { order_id, executed_quantity, .. }
+                if self.hidden.contains_key(order_id) =>
+            {
+                if let Some(order) = self.hidden.get_mut(order_id) {
+                    order.executed_quantity += *executed_quantity as u64;
+                    order.executions += 1;
                 }
+                self.execution_stats.hidden_volume += *executed_quantity as u64;
+                self.execution_stats.hidden_executions += 1;
             },
-            PitchMessage::OrderExecuted { order_id, executed_quantity, .. } => {
-                if let Some(order) = self.orders.get_mut(order_id) {
-                    if order.quantity >= *executed_quantity {
-                        order.quantity -= executed_quantity;
-                        if order.quantity == 0 {
-                            self.remove_order(*order_id);
-                        }
-                    }
+            PitchMessage::OrderExecuted { order_id, executed_quantity, .. }
+            | PitchMessage::OrderExecutedAtPrice { order_id, executed_quantity, .. } => {
+                self.reduce_order(*order_id, *executed_quantity)?;
+                self.execution_stats.displayed_volume += *executed_quantity as u64;
+                self.execution_stats.displayed_executions += 1;
+                // Order Executed at Price is not decoded yet and carries only the local clock
+                if let Some(timestamp) = message.exchange_timestamp() {
+                    self.last_update = Some(timestamp);
                 }
             },
-            PitchMessage::DeleteOrder { order_id, .. } => {
-                self.remove_order(*order_id);
+            PitchMessage::ReduceSize { order_id, .. } if self.hidden.contains_key(order_id) => {
+                // Nothing is displayed, so there is nothing to reduce
+            },
+            PitchMessage::ModifyOrder { order_id, quantity, price, timestamp } if self.hidden.contains_key(order_id) => {
+                self.modify_hidden_order(*order_id, *quantity, *price, *timestamp);
+            },
+            PitchMessage::DeleteOrder { order_id, .. } if self.hidden.contains_key(order_id) => {
+                self.hidden.remove(order_id);
+            },
+            PitchMessage::ReduceSize { order_id, cancelled_quantity, timestamp } => {
+                self.reduce_order(*order_id, *cancelled_quantity)?;
+                self.last_update = Some(*timestamp);
+            },
+            PitchMessage::ModifyOrder { order_id, quantity, price, timestamp } => {
+                self.modify_order(*order_id, *quantity, *price, *timestamp)?;
+                self.last_update = Some(*timestamp);
+            },
+            PitchMessage::DeleteOrder { order_id, timestamp } => {
+                self.remove_order(*order_id).ok_or(PitchError::UnknownOrder(*order_id))?;
+                self.last_update = Some(*timestamp);
             },
             _ => {
                 // Other messages
             }
         }
+        
+        Ok(())
     }
     
-    fn remove_order(&mut self, order_id: OrderId) {
-        if let Some(order) = self.orders.remove(&order_id) {
-            match order.side {
-                Side::Buy => {
-                    if let Some(orders) = self.bids.get_mut(&order.price.0) {
-                        orders.retain(|&id| id != order_id);
-                        if orders.is_empty() {
-                            self.bids.remove(&order.price.0);
-                        }
-                    }
-                },
-                Side::Sell => {
-                    if let Some(orders) = self.asks.get_mut(&order.price.0) {
-                        orders.retain(|&id| id != order_id);
-                        if orders.is_empty() {
-                            self.asks.remove(&order.price.0);
-                        }
-                    }
-                }
+    /// Purge every resting order, as on Unit Clear
+    pub fn clear(&mut self) {
+        if self.track_levels {
+            let prices: Vec<(Side, u64)> = self.levels(Side::Buy)
+                .chain(self.levels(Side::Sell))
+                .map(|level| (level.side(), level.price().0))
+                .collect();
+            for (side, price) in prices {
+                self.touch(side, price);
             }
         }
+        
+        self.orders.clear();
+        self.hidden.clear();
+        self.slab.clear();
+        self.bids.clear();
+        self.asks.clear();
+        self.state = BookState::Cleared;
+    }
+    
+    /// A modify that gives an undisclosed order a quantity makes it visible
+    fn modify_hidden_order(&mut self, order_id: OrderId, quantity: u32, price: Price, timestamp: DateTime<Utc>) {
+        if quantity == 0 {
+            if let Some(order) = self.hidden.get_mut(&order_id) {
+                order.price = price;
+                order.timestamp = timestamp;
+            }
+            return;
+        }
+        
+        if let Some(order) = self.hidden.remove(&order_id) {
+            self.last_update = Some(timestamp);
+            self.insert_order(OrderBookEntry {
+                order_id,
+                price,
+                quantity,
+                side: order.side,
+                pid: order.pid,
+                timestamp,
+            });
+        }
+    }
+    
+    /// Queue an order at the back of its level, as when restoring or repairing a book
+    pub(crate) fn restore_order(&mut self, order: OrderBookEntry) {
+        self.insert_order(order);
+    }
+    
+    pub(crate) fn restore_status(&mut self, trading_status: TradingStatus, state: BookState, last_update: Option<DateTime<Utc>>) {
+        self.trading_status = trading_status;
+        self.state = state;
+        self.last_update = last_update;
+    }
+    
+    /// New data after a clear or session end means the unit is publishing again
+    fn reopen(&mut self) {
+        if matches!(self.state, BookState::Cleared | BookState::Final) {
+            self.state = BookState::Live;
+        }
+    }
+    
+    fn side_and_slab(&mut self, side: Side) -> (&mut BookSide, &mut OrderSlab) {
+        match side {
+            Side::Buy => (&mut self.bids, &mut self.slab),
+            Side::Sell => (&mut self.asks, &mut self.slab),
+        }
+    }
+    
+    fn insert_order(&mut self, order: OrderBookEntry) {
+        // A reused order ID replaces the stale order rather than leaving it orphaned
+        self.remove_order(order.order_id);
+        
+        let (order_id, side, price) = (order.order_id, order.side, order.price.0);
+        let slot = self.slab.insert(OrderNode {
+            entry: order,
+            prev: None,
+            next: None,
+        });
+        self.orders.insert(order_id, slot);
+        self.touch(side, price);
+        
+        let (book_side, slab) = self.side_and_slab(side);
+        book_side.push_back(slab, slot);
+    }
+    
+    /// Take quantity off in place, so the order keeps its time priority
+    pub(crate) fn reduce_order(&mut self, order_id: OrderId, quantity: u32) -> Result<()> {
+        let node = self.orders
+            .get(&order_id)
+            .and_then(|&slot| self.slab.get_mut(slot))
+            .ok_or(PitchError::UnknownOrder(order_id))?;
+        let resting = node.entry.quantity;
+        
+        if quantity > resting {
+            // The exchange holds less than we do; drop the order rather than show phantom liquidity
+            self.remove_order(order_id);
+            return Err(PitchError::QuantityExceeded {
+                order_id,
+                resting,
+                requested: quantity,
+            });
+        }
+        
+        if quantity == resting {
+            self.remove_order(order_id);
+            return Ok(());
+        }
+        
+        node.entry.quantity -= quantity;
+        let (side, price) = (node.entry.side, node.entry.price.0);
+        self.touch(side, price);
+        self.side_and_slab(side).0.reduce(price, quantity);
+        
+        Ok(())
+    }
+    
+    /// Re-queue at the back of the new level: a modify always loses time priority
+    fn modify_order(&mut self, order_id: OrderId, quantity: u32, price: Price, timestamp: DateTime<Utc>) -> Result<()> {
+        let mut order = self.remove_order(order_id).ok_or(PitchError::UnknownOrder(order_id))?;
+        
+        if quantity > 0 {
+            order.quantity = quantity;
+            order.price = price;
+            order.timestamp = timestamp;
+            self.insert_order(order);
+        }
+        
+        Ok(())
+    }
+    
+    pub(crate) fn remove_order(&mut self, order_id: OrderId) -> Option<OrderBookEntry> {
+        let slot = self.orders.remove(&order_id)?;
+        let entry = &self.slab.get(slot)?.entry;
+        let (side, price) = (entry.side, entry.price.0);
+        self.touch(side, price);
+        
+        let (book_side, slab) = self.side_and_slab(side);
+        book_side.unlink(slab, slot)
+    }
+    
+    pub(crate) fn remove_hidden_order(&mut self, order_id: OrderId) -> Option<HiddenOrder> {
+        self.hidden.remove(&order_id)
+    }
+    
+    /// Remember a level's state before its first change in the current batch
+    fn touch(&mut self, side: Side, price: u64) {
+        if !self.track_levels || self.touched.contains_key(&(side, price)) {
+            return;
+        }
+        
+        let before = self.book_side(side)
+            .levels
+            .get(&price)
+            .map(|level| (level.quantity, level.count));
+        self.touched.insert((side, price), before);
+    }
+    
+    /// Start or stop recording level changes for `take_level_updates`
+    pub fn set_level_tracking(&mut self, enabled: bool) {
+        self.track_levels = enabled;
+        if !enabled {
+            self.touched.clear();
+        }
+    }
+    
+    pub fn has_level_updates(&self) -> bool {
+        !self.touched.is_empty()
+    }
+    
+    /// Net level changes since the last call, bids then asks, best price first.
+    ///
+    /// Changes are coalesced, so a level that is emptied and refilled within the
+    /// batch shows up as one update. `level_index` is the position in the book as
+    /// it stands now; for a deleted level it is where the level would have been.
+    pub fn take_level_updates(&mut self) -> Vec<LevelUpdate> {
+        let mut updates: Vec<(u64, LevelUpdate)> = Vec::with_capacity(self.touched.len());
+        
+        for ((side, price), before) in std::mem::take(&mut self.touched) {
+            let book_side = self.book_side(side);
+            let after = book_side.levels.get(&price).map(|level| (level.quantity, level.count));
+            
+            let action = match (before, after) {
+                (None, Some(_)) => LevelAction::Insert,
+                (Some(_), None) => LevelAction::Delete,
+                (Some(before), Some(after)) if before != after => LevelAction::Update,
+                _ => continue,
+            };
+            
+            let key = priority_key(side, price);
+            let (quantity, order_count) = after.unwrap_or((0, 0));
+            updates.push((key, LevelUpdate {
+                side,
+                price: Price(price),
+                quantity,
+                order_count,
+                level_index: book_side.priority.range(..key).count(),
+                action,
+            }));
+        }
+        
+        updates.sort_by_key(|(key, update)| (update.side == Side::Sell, *key));
+        updates.into_iter().map(|(_, update)| update).collect()
     }
     
     pub fn best_bid(&self) -> Option<Price> {
-        self.bids.keys().last().map(|&price| Price(price))
+        self.bids.best.map(Price)
     }
     
     pub fn best_ask(&self) -> Option<Price> {
-        self.asks.keys().next().map(|&price| Price(price))
+        self.asks.best.map(Price)
     }
     
     pub fn spread(&self) -> Option<Price> {
@@ -125,30 +681,17 @@ impl OrderBook {
         }
     }
     
-    pub fn get_level_info(&self, levels: usize) -> (Vec<(Price, u32)>, Vec<(Price, u32)>) {
-        let bids: Vec<(Price, u32)> = self.bids
+    pub fn get_level_info(&self, levels: usize) -> (LevelInfo, LevelInfo) {
+        let bids: LevelInfo = self.bids
             .iter()
-            .rev()
             .take(levels)
-            .map(|(&price_raw, order_ids)| {
-                let total_qty = order_ids.iter()
-                    .filter_map(|&id| self.orders.get(&id))
-                    .map(|order| order.quantity)
-                    .sum();
-                (Price(price_raw), total_qty)
-            })
+            .map(|(price, level)| (Price(price), level.quantity))
             .collect();
             
-        let asks: Vec<(Price, u32)> = self.asks
+        let asks: LevelInfo = self.asks
             .iter()
             .take(levels)
-            .map(|(&price_raw, order_ids)| {
-                let total_qty = order_ids.iter()
-                    .filter_map(|&id| self.orders.get(&id))
-                    .map(|order| order.quantity)
-                    .sum();
-                (Price(price_raw), total_qty)
-            })
+            .map(|(price, level)| (Price(price), level.quantity))
             .collect();
             
         (bids, asks)
@@ -162,21 +705,539 @@ impl OrderBook {
         self.trading_status
     }
     
+    /// Walk every queue and check it against the index and cached aggregates.
+    /// O(orders); meant for audits rather than the hot path.
+    pub fn diagnose(&self) -> Vec<DiagnosticIssue> {
+        let mut issues = Vec::new();
+        let mut queued = HashSet::with_capacity(self.orders.len());
+        
+        for book_side in [&self.bids, &self.asks] {
+            let side = book_side.side;
+            let mut side_quantity = 0u64;
+            
+            let mut prices: Vec<u64> = book_side.levels.keys().copied().collect();
+            prices.sort_by_key(|&price| priority_key(side, price));
+            
+            for price in prices {
+                let level = &book_side.levels[&price];
+                let (quantity, count) = self.diagnose_level(side, price, level, &mut queued, &mut issues);
+                side_quantity += quantity;
+                
+                if quantity != level.quantity {
+                    issues.push(DiagnosticIssue::LevelQuantityMismatch {
+                        side,
+                        price: Price(price),
+                        cached: level.quantity,
+                        actual: quantity,
+                    });
+                }
+                if count != level.count {
+                    issues.push(DiagnosticIssue::LevelCountMismatch {
+                        side,
+                        price: Price(price),
+                        cached: level.count,
+                        actual: count,
+                    });
+                }
+            }
+            
+            if side_quantity != book_side.quantity {
+                issues.push(DiagnosticIssue::SideQuantityMismatch {
+                    side,
+                    cached: book_side.quantity,
+                    actual: side_quantity,
+                });
+            }
+            
+            let actual_best = book_side.levels.keys().copied().min_by_key(|&price| priority_key(side, price));
+            let priority_best = book_side.priority.first().map(|&key| priority_key(side, key));
+            if book_side.best != actual_best || priority_best != actual_best {
+                issues.push(DiagnosticIssue::BestPriceMismatch {
+                    side,
+                    cached: book_side.best.map(Price),
+                    actual: actual_best.map(Price),
+                });
+            }
+        }
+        
+        let mut unqueued: Vec<OrderId> = self.orders
+            .keys()
+            .filter(|order_id| !queued.contains(*order_id))
+            .copied()
+            .collect();
+        unqueued.sort_by_key(|order_id| order_id.0);
+        issues.extend(unqueued.into_iter().map(DiagnosticIssue::UnqueuedOrder));
+        
+        // Auction call phases accept crossing interest; continuous trading must not
+        if !self.trading_status.is_auction() {
+            match (self.best_bid(), self.best_ask()) {
+                (Some(bid), Some(ask)) if bid.0 > ask.0 => issues.push(DiagnosticIssue::CrossedMarket { bid, ask }),
+                (Some(bid), Some(ask)) if bid.0 == ask.0 => issues.push(DiagnosticIssue::LockedMarket { price: bid }),
+                _ => {},
+            }
+        }
+        
+        issues
+    }
+    
+    /// Walk one level's queue; returns the quantity and count actually queued
+    fn diagnose_level(
+        &self,
+        side: Side,
+        price: u64,
+        level: &Level,
+        queued: &mut HashSet<OrderId>,
+        issues: &mut Vec<DiagnosticIssue>,
+    ) -> (u64, usize) {
+        let (mut quantity, mut count) = (0u64, 0usize);
+        let mut prev = None;
+        let mut next = level.head;
+        
+        while let Some(slot) = next {
+            // A cycle would otherwise loop forever
+            let Some(node) = self.slab.get(slot).filter(|_| count < self.slab.slots.len()) else {
+                issues.push(DiagnosticIssue::BrokenQueue { side, price: Price(price) });
+                return (quantity, count);
+            };
+            let order = &node.entry;
+            
+            if node.prev != prev {
+                issues.push(DiagnosticIssue::BrokenQueue { side, price: Price(price) });
+            }
+            if self.orders.get(&order.order_id) != Some(&slot) {
+                issues.push(DiagnosticIssue::OrphanedOrder(order.order_id));
+            }
+            if !queued.insert(order.order_id) {
+                issues.push(DiagnosticIssue::DuplicateOrder(order.order_id));
+            }
+            if order.quantity == 0 {
+                issues.push(DiagnosticIssue::ZeroQuantity(order.order_id));
+            }
+            if order.side != side || order.price.0 != price {
+                issues.push(DiagnosticIssue::MisplacedOrder {
+                    order_id: order.order_id,
+                    side: order.side,
+                    price: order.price,
+                    level_side: side,
+                    level_price: Price(price),
+                });
+            }
+            
+            quantity += order.quantity as u64;
+            count += 1;
+            prev = Some(slot);
+            next = node.next;
+        }
+        
+        if level.tail != prev {
+            issues.push(DiagnosticIssue::BrokenQueue { side, price: Price(price) });
+        }
+        
+        (quantity, count)
+    }
+    
+    /// Exchange time of the last message applied to this book
+    pub fn last_update(&self) -> Option<DateTime<Utc>> {
+        self.last_update
+    }
+    
+    pub fn state(&self) -> BookState {
+        self.state
+    }
+    
+    pub fn set_state(&mut self, state: BookState) {
+        self.state = state;
+    }
+    
+    fn book_side(&self, side: Side) -> &BookSide {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+    
+    /// Levels on one side, best price first
+    pub fn levels(&self, side: Side) -> impl Iterator<Item = PriceLevel<'_>> {
+        self.book_side(side).iter().map(move |(price, level)| PriceLevel {
+            side,
+            price: Price(price),
+            level,
+            slab: &self.slab,
+        })
+    }
+    
+    pub fn level(&self, side: Side, price: Price) -> Option<PriceLevel<'_>> {
+        let level = self.book_side(side).levels.get(&price.0)?;
+        Some(PriceLevel {
+            side,
+            price,
+            level,
+            slab: &self.slab,
+        })
+    }
+    
+    /// Every resting order, by side then price-time priority (bids first)
+    pub fn orders(&self) -> impl Iterator<Item = &OrderBookEntry> {
+        self.levels(Side::Buy)
+            .chain(self.levels(Side::Sell))
+            .flat_map(|level| level.orders())
+    }
+    
+    /// Orders and shares queued ahead of an order at its price; O(orders ahead)
+    pub fn queue_position(&self, order_id: OrderId) -> Option<QueuePosition> {
+        let &slot = self.orders.get(&order_id)?;
+        let node = self.slab.get(slot)?;
+        let level = self.book_side(node.entry.side).levels.get(&node.entry.price.0)?;
+        
+        let mut position = QueuePosition {
+            orders_ahead: 0,
+            shares_ahead: 0,
+            level_order_count: level.count,
+            level_quantity: level.quantity,
+        };
+        
+        let mut prev = node.prev;
+        while let Some(ahead) = prev.and_then(|slot| self.slab.get(slot)) {
+            position.orders_ahead += 1;
+            position.shares_ahead += ahead.entry.quantity as u64;
+            prev = ahead.prev;
+        }
+        
+        Some(position)
+    }
+    
+    pub fn contains_order(&self, order_id: OrderId) -> bool {
+        self.orders.contains_key(&order_id)
+    }
+    
+    pub fn order(&self, order_id: OrderId) -> Option<&OrderBookEntry> {
+        let &slot = self.orders.get(&order_id)?;
+        self.slab.get(slot).map(|node| &node.entry)
+    }
+    
+    pub fn contains_hidden_order(&self, order_id: OrderId) -> bool {
+        self.hidden.contains_key(&order_id)
+    }
+    
+    pub fn hidden_order(&self, order_id: OrderId) -> Option<&HiddenOrder> {
+        self.hidden.get(&order_id)
+    }
+    
+    /// Undisclosed orders, in no particular order
+    pub fn hidden_orders(&self) -> impl Iterator<Item = &HiddenOrder> {
+        self.hidden.values()
+    }
+    
+    pub fn hidden_order_count(&self) -> usize {
+        self.hidden.len()
+    }
+    
+    pub fn execution_stats(&self) -> ExecutionStats {
+        self.execution_stats
+    }
+    
+    pub(crate) fn restore_hidden_order(&mut self, order: HiddenOrder) {
+        self.hidden.insert(order.order_id, order);
+    }
+    
+    pub(crate) fn restore_execution_stats(&mut self, stats: ExecutionStats) {
+        self.execution_stats = stats;
+    }
+    
     pub fn order_count(&self) -> usize {
         self.orders.len()
     }
     
-    pub fn total_bid_quantity(&self) -> u32 {
-        self.orders.values()
-            .filter(|order| order.side == Side::Buy)
-            .map(|order| order.quantity)
-            .sum()
+    pub fn total_bid_quantity(&self) -> u64 {
+        self.bids.quantity
+    }
+    
+    pub fn total_ask_quantity(&self) -> u64 {
+        self.asks.quantity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn add(order_id: u64, side: Side, quantity: u32, price: u64) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: at(order_id as i64),
+            order_id: OrderId(order_id),
+            side,
+            quantity,
+            symbol: "AAPL".to_string(),
+            price: Price(price),
+            pid: "TEST".to_string(),
+        }
+    }
+    
+    fn execute(order_id: u64, quantity: u32) -> PitchMessage {
+        PitchMessage::OrderExecuted {
+            timestamp: at(50),
+            order_id: OrderId(order_id),
+            executed_quantity: quantity,
+            execution_id: ExecutionId(1),
+            contra_order_id: OrderId(0),
+            contra_pid: String::new(),
+        }
+    }
+    
+    fn reduce(order_id: u64, quantity: u32) -> PitchMessage {
+        PitchMessage::ReduceSize {
+            timestamp: at(50),
+            order_id: OrderId(order_id),
+            cancelled_quantity: quantity,
+        }
+    }
+    
+    fn modify(order_id: u64, quantity: u32, price: u64) -> PitchMessage {
+        PitchMessage::ModifyOrder {
+            timestamp: at(50),
+            order_id: OrderId(order_id),
+            quantity,
+            price: Price(price),
+        }
+    }
+    
+    fn delete(order_id: u64) -> PitchMessage {
+        PitchMessage::DeleteOrder {
+            timestamp: at(50),
+            order_id: OrderId(order_id),
+        }
+    }
+    
+    fn book(messages: &[PitchMessage]) -> OrderBook {
+        let mut book = OrderBook::new("AAPL".to_string());
+        for message in messages {
+            book.apply_message(message).unwrap();
+        }
+        book
+    }
+    
+    fn queue(book: &OrderBook, side: Side, price: u64) -> Vec<(u64, u32)> {
+        book.level(side, Price(price))
+            .map(|level| level.orders().map(|order| (order.order_id.0, order.quantity)).collect())
+            .unwrap_or_default()
+    }
+    
+    #[test]
+    fn reduce_size_keeps_priority() {
+        let mut book = book(&[add(1, Side::Buy, 100, 1_000), add(2, Side::Buy, 100, 1_000)]);
+        
+        book.apply_message(&reduce(1, 30)).unwrap();
+        assert_eq!(queue(&book, Side::Buy, 1_000), vec![(1, 70), (2, 100)]);
+        assert_eq!(book.level(Side::Buy, Price(1_000)).unwrap().quantity(), 170);
+        
+        // Reducing the whole size removes the order
+        book.apply_message(&reduce(1, 70)).unwrap();
+        assert_eq!(queue(&book, Side::Buy, 1_000), vec![(2, 100)]);
+        assert!(!book.contains_order(OrderId(1)));
+    }
+    
+    #[test]
+    fn modify_loses_priority() {
+        let mut book = book(&[add(1, Side::Buy, 100, 1_000), add(2, Side::Buy, 100, 1_000)]);
+        
+        book.apply_message(&modify(1, 60, 1_000)).unwrap();
+        assert_eq!(queue(&book, Side::Buy, 1_000), vec![(2, 100), (1, 60)]);
+        
+        book.apply_message(&modify(2, 100, 1_010)).unwrap();
+        assert_eq!(queue(&book, Side::Buy, 1_000), vec![(1, 60)]);
+        assert_eq!(queue(&book, Side::Buy, 1_010), vec![(2, 100)]);
+        assert_eq!(book.best_bid(), Some(Price(1_010)));
+    }
+    
+    #[test]
+    fn execution_reduces_then_removes() {
+        let mut book = book(&[add(1, Side::Sell, 100, 1_000)]);
+        
+        book.apply_message(&execute(1, 40)).unwrap();
+        assert_eq!(book.order(OrderId(1)).unwrap().quantity, 60);
+        book.apply_message(&execute(1, 60)).unwrap();
+        assert!(!book.contains_order(OrderId(1)));
+        assert_eq!(book.best_ask(), None);
+    }
+    
+    #[test]
+    fn updates_to_unknown_orders_are_errors() {
+        let mut book = book(&[add(1, Side::Buy, 100, 1_000)]);
+        
+        for message in [execute(9, 10), reduce(9, 10), modify(9, 10, 1_000), delete(9)] {
+            assert!(matches!(book.apply_message(&message), Err(PitchError::UnknownOrder(OrderId(9)))), "{:?}", message);
+        }
+        assert_eq!(queue(&book, Side::Buy, 1_000), vec![(1, 100)]);
+    }
+    
+    #[test]
+    fn taking_more_than_rests_is_an_error_and_drops_the_order() {
+        for message in [execute(1, 150), reduce(1, 101)] {
+            let mut book = book(&[add(1, Side::Buy, 100, 1_000), add(2, Side::Buy, 20, 1_000)]);
+            
+            let result = book.apply_message(&message);
+            assert!(matches!(result, Err(PitchError::QuantityExceeded { order_id: OrderId(1), resting: 100, .. })));
+            assert_eq!(queue(&book, Side::Buy, 1_000), vec![(2, 20)]);
+            assert_eq!(book.total_bid_quantity(), 20);
+        }
+    }
+    
+    #[test]
+    fn failed_updates_leave_last_update_alone() {
+        let mut book = book(&[add(1, Side::Buy, 100, 1_000)]);
+        
+        assert!(book.apply_message(&delete(9)).is_err());
+        assert!(book.apply_message(&execute(1, 500)).is_err());
+        assert_eq!(book.last_update(), Some(at(1)));
+    }
+    
+    #[test]
+    fn queue_position_counts_orders_and_shares_ahead() {
+        let book = book(&[
+            add(1, Side::Buy, 100, 1_000),
+            add(2, Side::Buy, 200, 1_000),
+            add(3, Side::Buy, 50, 1_000),
+            add(4, Side::Buy, 10, 990),
+        ]);
+        
+        assert_eq!(book.queue_position(OrderId(3)), Some(QueuePosition {
+            orders_ahead: 2,
+            shares_ahead: 300,
+            level_order_count: 3,
+            level_quantity: 350,
+        }));
+        assert_eq!(book.queue_position(OrderId(4)).unwrap().orders_ahead, 0);
+        assert_eq!(book.queue_position(OrderId(9)), None);
+    }
+    
+    #[test]
+    fn levels_and_orders_iterate_best_price_first_in_time_priority() {
+        let book = book(&[
+            add(1, Side::Buy, 100, 1_000),
+            add(2, Side::Buy, 200, 1_010),
+            add(3, Side::Buy, 300, 1_000),
+            add(4, Side::Sell, 400, 1_030),
+            add(5, Side::Sell, 500, 1_020),
+            add(6, Side::Sell, 600, 1_020),
+            delete(2),
+            add(7, Side::Buy, 700, 1_010),
+        ]);
+        
+        let bids: Vec<(u64, u64, usize)> = book
+            .levels(Side::Buy)
+            .map(|level| (level.price().0, level.quantity(), level.order_count()))
+            .collect();
+        assert_eq!(bids, vec![(1_010, 700, 1), (1_000, 400, 2)]);
+        
+        let asks: Vec<u64> = book.levels(Side::Sell).map(|level| level.price().0).collect();
+        assert_eq!(asks, vec![1_020, 1_030]);
+        assert!(book.levels(Side::Sell).all(|level| level.side() == Side::Sell));
+        
+        let orders: Vec<u64> = book.orders().map(|order| order.order_id.0).collect();
+        assert_eq!(orders, vec![7, 1, 3, 5, 6, 4]);
+        assert!(book.level(Side::Buy, Price(1_020)).is_none());
+    }
+    
+    fn status(trading_status: TradingStatus) -> PitchMessage {
+        PitchMessage::TradingStatus {
+            timestamp: at(60),
+            symbol: "AAPL".to_string(),
+            trading_status,
+            market_id_code: String::new(),
+        }
+    }
+    
+    #[test]
+    fn crossed_and_locked_markets_are_flagged_outside_auctions() {
+        let mut book = book(&[status(TradingStatus::Trading), add(1, Side::Buy, 100, 1_000), add(2, Side::Sell, 100, 1_010)]);
+        assert!(book.diagnose().is_empty());
+        
+        book.apply_message(&add(3, Side::Sell, 100, 1_000)).unwrap();
+        assert_eq!(book.diagnose(), vec![DiagnosticIssue::LockedMarket { price: Price(1_000) }]);
+        
+        book.apply_message(&add(4, Side::Buy, 100, 1_005)).unwrap();
+        assert_eq!(book.diagnose(), vec![DiagnosticIssue::CrossedMarket { bid: Price(1_005), ask: Price(1_000) }]);
+        
+        // Auction call phases legitimately cross
+        for auction in [TradingStatus::PreOpen, TradingStatus::PreClose, TradingStatus::Halted] {
+            book.apply_message(&status(auction)).unwrap();
+            assert!(book.diagnose().is_empty(), "{:?}", auction);
+        }
+        
+        book.apply_message(&status(TradingStatus::Trading)).unwrap();
+        assert_eq!(book.diagnose().len(), 1);
+    }
+    
+    fn trade(order_id: u64, contra_order_id: u64, quantity: u32) -> PitchMessage {
+        PitchMessage::Trade {
+            timestamp: at(70),
+            symbol: "AAPL".to_string(),
+            quantity,
+            price: Price(1_000),
+            execution_id: ExecutionId(2),
+            order_id: OrderId(order_id),
+            contra_order_id: OrderId(contra_order_id),
+            pid: String::new(),
+            contra_pid: String::new(),
+            trade_type: ' ',
+            trade_designation: ' ',
+            trade_report_type: ' ',
+            trade_transaction_time: at(70),
+            flags: 0,
+        }
+    }
+    
+    #[test]
+    fn undisclosed_orders_stay_off_the_levels_until_modified_visible() {
+        let mut book = book(&[add(1, Side::Buy, 0, 1_000), add(2, Side::Buy, 100, 990)]);
+        
+        assert!(book.contains_hidden_order(OrderId(1)));
+        assert!(!book.contains_order(OrderId(1)));
+        assert_eq!(book.best_bid(), Some(Price(990)));
+        assert_eq!(book.hidden_order_count(), 1);
+        
+        // A zero-quantity modify only moves it
+        book.apply_message(&modify(1, 0, 1_005)).unwrap();
+        assert_eq!(book.hidden_order(OrderId(1)).unwrap().price, Price(1_005));
+        assert_eq!(book.best_bid(), Some(Price(990)));
+        
+        book.apply_message(&modify(1, 300, 1_010)).unwrap();
+        assert!(!book.contains_hidden_order(OrderId(1)));
+        assert_eq!(queue(&book, Side::Buy, 1_010), vec![(1, 300)]);
+        assert_eq!(book.best_bid(), Some(Price(1_010)));
     }
     
-    pub fn total_ask_quantity(&self) -> u32 {
-        self.orders.values()
-            .filter(|order| order.side == Side::Sell)
-            .map(|order| order.quantity)
-            .sum()
+    #[test]
+    fn executions_are_split_between_displayed_and_hidden() {
+        let mut book = book(&[add(1, Side::Buy, 0, 1_000), add(2, Side::Buy, 100, 1_000)]);
+        
+        book.apply_message(&execute(1, 30)).unwrap();
+        book.apply_message(&trade(9, 1, 20)).unwrap(); // Hidden order on the contra side
+        book.apply_message(&execute(2, 40)).unwrap();
+        book.apply_message(&trade(8, 9, 10)).unwrap(); // Neither side known
+        
+        let hidden = book.hidden_order(OrderId(1)).unwrap();
+        assert_eq!(hidden.executed_quantity, 50);
+        assert_eq!(hidden.executions, 2);
+        assert_eq!(book.execution_stats(), ExecutionStats {
+            displayed_volume: 40,
+            displayed_executions: 1,
+            hidden_volume: 50,
+            hidden_executions: 2,
+            unattributed_volume: 10,
+            unattributed_trades: 1,
+        });
+        assert_eq!(book.execution_stats().hidden_ratio(), Some(50.0 / 90.0));
+        
+        // Reducing a hidden order is a no-op and deleting it forgets it
+        book.apply_message(&reduce(1, 10)).unwrap();
+        book.apply_message(&delete(1)).unwrap();
+        assert_eq!(book.hidden_order_count(), 0);
+        assert_eq!(queue(&book, Side::Buy, 1_000), vec![(2, 60)]);
     }
 }
@@ -3,6 +3,7 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use chrono::{DateTime, Utc};
 use std::io::{Cursor, Read};
 
+#[derive(Debug)]
 pub struct PitchParser {
     buffer: Vec<u8>,
     position: usize,
@@ -42,16 +43,64 @@ impl PitchParser {
         Ok(Some((header, messages)))
     }
     
-    fn parse_header(&mut self) -> Result<SequencedUnitHeader> {
-        let mut cursor = Cursor::new(&self.buffer[self.position..]);
+    /// Parse one complete Sequenced Unit frame straight from `data` (e.g. a
+    /// single UDP datagram) without copying it into the internal buffer.
+    pub fn parse_frame(&self, data: &[u8]) -> Result<(SequencedUnitHeader, Vec<PitchMessage>)> {
+        let header = Self::read_header(data)?;
+        let length = header.length as usize;
+        
+        // The length covers the header itself, so anything shorter is corrupt
+        if length < 8 {
+            return Err(PitchError::InsufficientData {
+                expected: 8,
+                actual: length,
+            });
+        }
+        
+        if data.len() < length {
+            return Err(PitchError::InsufficientData {
+                expected: length,
+                actual: data.len(),
+            });
+        }
+        
+        let mut offset = 8;
+        let mut messages = Vec::with_capacity(header.count as usize);
+        
+        for _ in 0..header.count {
+            let remaining = &data[offset..length];
+            let message_length = remaining.first().map_or(0, |&len| len as usize);
+            
+            if message_length < 2 || message_length > remaining.len() {
+                return Err(PitchError::InsufficientData {
+                    expected: message_length.max(2),
+                    actual: remaining.len(),
+                });
+            }
+            
+            messages.push(self.decode_message(&remaining[..message_length])?);
+            offset += message_length;
+        }
+        
+        Ok((header, messages))
+    }
+    
+    /// Decode the 8-byte Sequenced Unit Header at the start of `data`.
+    pub fn read_header(data: &[u8]) -> Result<SequencedUnitHeader> {
+        if data.len() < 8 {
+            return Err(PitchError::InsufficientData {
+                expected: 8,
+                actual: data.len(),
+            });
+        }
+        
+        let mut cursor = Cursor::new(data);
         
         let length = cursor.read_u16::<LittleEndian>()?;
         let count = cursor.read_u8()?;
         let unit = cursor.read_u8()?;
         let sequence = cursor.read_u32::<LittleEndian>()?;
         
-        self.position += 8;
-        
         Ok(SequencedUnitHeader {
             length,
             count,
@@ -60,6 +109,37 @@ impl PitchParser {
         })
     }
     
+    /// Exchange timestamp in nanoseconds of the first timestamped message in a
+    /// raw frame, read without decoding the messages
+    pub fn first_timestamp_nanos(frame: &[u8]) -> Option<u64> {
+        let header = Self::read_header(frame).ok()?;
+        let end = (header.length as usize).min(frame.len());
+        let mut offset = 8;
+        
+        for _ in 0..header.count {
+            let length = *frame.get(offset)? as usize;
+            if length < 2 || offset + length > end {
+                return None;
+            }
+            
+            let message_type = frame[offset + 1];
+            if PitchMessage::has_exchange_timestamp(message_type) && length >= 10 {
+                let mut cursor = Cursor::new(&frame[offset + 2..offset + 10]);
+                return cursor.read_u64::<LittleEndian>().ok();
+            }
+            
+            offset += length;
+        }
+        
+        None
+    }
+    
+    fn parse_header(&mut self) -> Result<SequencedUnitHeader> {
+        let header = Self::read_header(&self.buffer[self.position..])?;
+        self.position += 8;
+        Ok(header)
+    }
+    
     fn parse_message(&mut self) -> Result<Option<PitchMessage>> {
         if self.position >= self.buffer.len() {
             return Ok(None);
@@ -71,37 +151,47 @@ impl PitchParser {
             return Ok(None);
         }
         
-        let message_type = self.buffer[self.position + 1];
-        let message_data = &self.buffer[self.position..self.position + length];
-        
-        let message = match message_type {
-            0x97 => self.parse_unit_clear(message_data)?,
-            0x3B => self.parse_trading_status(message_data)?,
-            0x37 => self.parse_add_order(message_data)?,
-            0x38 => self.parse_order_executed(message_data)?,
-            0x58 => self.parse_order_executed_at_price(message_data)?,
-            0x39 => self.parse_reduce_size(message_data)?,
-            0x3A => self.parse_modify_order(message_data)?,
-            0x3C => self.parse_delete_order(message_data)?,
-            0x3D => self.parse_trade(message_data)?,
-            0x3E => self.parse_trade_break(message_data)?,
-            0xE3 => self.parse_calculated_value(message_data)?,
-            0x2D => self.parse_end_of_session(message_data)?,
-            0x59 => self.parse_auction_update(message_data)?,
-            0x5A => self.parse_auction_summary(message_data)?,
-            _ => return Err(PitchError::InvalidMessageType(message_type)),
-        };
+        let message = self.decode_message(&self.buffer[self.position..self.position + length])?;
         
         self.position += length;
         Ok(Some(message))
     }
     
+    fn decode_message(&self, message_data: &[u8]) -> Result<PitchMessage> {
+        if message_data.len() < 2 {
+            return Err(PitchError::InsufficientData {
+                expected: 2,
+                actual: message_data.len(),
+            });
+        }
+        
+        let message_type = message_data[1];
+        
+        match message_type {
+            0x97 => self.parse_unit_clear(message_data),
+            0x3B => self.parse_trading_status(message_data),
+            0x37 => self.parse_add_order(message_data),
+            0x38 => self.parse_order_executed(message_data),
+            0x58 => self.parse_order_executed_at_price(message_data),
+            0x39 => self.parse_reduce_size(message_data),
+            0x3A => self.parse_modify_order(message_data),
+            0x3C => self.parse_delete_order(message_data),
+            0x3D => self.parse_trade(message_data),
+            0x3E => self.parse_trade_break(message_data),
+            0xE3 => self.parse_calculated_value(message_data),
+            0x2D => self.parse_end_of_session(message_data),
+            0x59 => self.parse_auction_update(message_data),
+            0x5A => self.parse_auction_summary(message_data),
+            _ => Err(PitchError::InvalidMessageType(message_type)),
+        }
+    }
+    
     fn parse_timestamp(&self, cursor: &mut Cursor<&[u8]>) -> Result<DateTime<Utc>> {
         let nanos = cursor.read_u64::<LittleEndian>()?;
         let timestamp = DateTime::from_timestamp(
             (nanos / 1_000_000_000) as i64,
             (nanos % 1_000_000_000) as u32,
-        ).unwrap_or_else(|| Utc::now());
+        ).unwrap_or_else(Utc::now);
         
         Ok(timestamp)
     }
@@ -110,7 +200,7 @@ impl PitchParser {
         String::from_utf8_lossy(data).trim_end().to_string()
     }
     
-    fn parse_unit_clear(&self, data: &[u8]) -> Result<PitchMessage> {
+    fn parse_unit_clear(&self, _data: &[u8]) -> Result<PitchMessage> {
         Ok(PitchMessage::UnitClear {
             timestamp: Utc::now(),
         })
@@ -251,23 +341,50 @@ impl PitchParser {
         })
     }
     
-    fn parse_trade(&self, _data: &[u8]) -> Result<PitchMessage> {
-        // Simplified implementation
+    fn parse_trade(&self, data: &[u8]) -> Result<PitchMessage> {
+        let mut cursor = Cursor::new(&data[2..]);
+        
+        let timestamp = self.parse_timestamp(&mut cursor)?;
+        
+        let mut symbol_bytes = [0u8; 6];
+        cursor.read_exact(&mut symbol_bytes)?;
+        let symbol = self.parse_string(&symbol_bytes);
+        
+        let quantity = cursor.read_u32::<LittleEndian>()?;
+        let price = Price(cursor.read_u64::<LittleEndian>()?);
+        let execution_id = ExecutionId(cursor.read_u64::<LittleEndian>()?);
+        let order_id = OrderId(cursor.read_u64::<LittleEndian>()?);
+        let contra_order_id = OrderId(cursor.read_u64::<LittleEndian>()?);
+        
+        let mut pid_bytes = [0u8; 4];
+        cursor.read_exact(&mut pid_bytes)?;
+        let pid = self.parse_string(&pid_bytes);
+        
+        let mut contra_pid_bytes = [0u8; 4];
+        cursor.read_exact(&mut contra_pid_bytes)?;
+        let contra_pid = self.parse_string(&contra_pid_bytes);
+        
+        let trade_type = cursor.read_u8()? as char;
+        let trade_designation = cursor.read_u8()? as char;
+        let trade_report_type = cursor.read_u8()? as char;
+        let trade_transaction_time = self.parse_timestamp(&mut cursor)?;
+        let flags = cursor.read_u8()?;
+        
         Ok(PitchMessage::Trade {
-            timestamp: Utc::now(),
-            symbol: String::new(),
-            quantity: 0,
-            price: Price(0),
-            execution_id: ExecutionId(0),
-            order_id: OrderId(0),
-            contra_order_id: OrderId(0),
-            pid: String::new(),
-            contra_pid: String::new(),
-            trade_type: 'N',
-            trade_designation: 'C',
-            trade_report_type: ' ',
-            trade_transaction_time: Utc::now(),
-            flags: 0,
+            timestamp,
+            symbol,
+            quantity,
+            price,
+            execution_id,
+            order_id,
+            contra_order_id,
+            pid,
+            contra_pid,
+            trade_type,
+            trade_designation,
+            trade_report_type,
+            trade_transaction_time,
+            flags,
         })
     }
     
@@ -327,3 +444,85 @@ impl Default for PitchParser {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::PitchSimulator;
+    use chrono::TimeZone;
+    
+    fn delete_frame(count: usize) -> Vec<u8> {
+        let messages: Vec<PitchMessage> = (0..count)
+            .map(|i| PitchMessage::DeleteOrder {
+                timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000),
+                order_id: OrderId(i as u64),
+            })
+            .collect();
+        let header = SequencedUnitHeader { length: 0, count: count as u8, unit: 1, sequence: 10 };
+        PitchSimulator::new().serialize_frame(&header, &messages).unwrap()
+    }
+    
+    #[test]
+    fn parse_frame_decodes_every_message() {
+        let (header, messages) = PitchParser::new().parse_frame(&delete_frame(3)).unwrap();
+        
+        assert_eq!(header.length, 8 + 3 * 18);
+        assert_eq!(header.sequence, 10);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[2], PitchMessage::DeleteOrder { order_id: OrderId(2), .. }));
+    }
+    
+    #[test]
+    fn parse_frame_rejects_header_shorter_than_itself() {
+        for length in 0..8u16 {
+            let mut frame = delete_frame(1);
+            frame[..2].copy_from_slice(&length.to_le_bytes());
+            
+            let result = PitchParser::new().parse_frame(&frame);
+            assert!(matches!(result, Err(PitchError::InsufficientData { expected: 8, .. })), "length {}", length);
+        }
+    }
+    
+    #[test]
+    fn parse_frame_rejects_short_data() {
+        let frame = delete_frame(2);
+        
+        assert!(PitchParser::new().parse_frame(&frame[..5]).is_err());
+        assert!(PitchParser::new().parse_frame(&frame[..frame.len() - 1]).is_err());
+    }
+    
+    #[test]
+    fn parse_frame_accepts_zero_count_heartbeat() {
+        let (header, messages) = PitchParser::new().parse_frame(&delete_frame(0)).unwrap();
+        
+        assert!(header.is_heartbeat());
+        assert!(messages.is_empty());
+    }
+    
+    #[test]
+    fn parse_frame_rejects_messages_beyond_frame_length() {
+        // Count claims more messages than the length covers
+        let mut frame = delete_frame(2);
+        frame[2] = 3;
+        frame.extend_from_slice(&delete_frame(1)[8..]);
+        
+        assert!(PitchParser::new().parse_frame(&frame).is_err());
+    }
+    
+    #[test]
+    fn parse_frame_ignores_bytes_after_frame_length() {
+        let mut frame = delete_frame(1);
+        frame.extend_from_slice(&[0xFF; 32]);
+        
+        let (_, messages) = PitchParser::new().parse_frame(&frame).unwrap();
+        assert_eq!(messages.len(), 1);
+    }
+    
+    #[test]
+    fn parse_frame_rejects_zero_length_message() {
+        let mut frame = delete_frame(1);
+        frame[8] = 0;
+        
+        assert!(PitchParser::new().parse_frame(&frame).is_err());
+    }
+}
@@ -0,0 +1,1093 @@
+use crate::{error::*, message::*, parser::PitchParser, simulator::PitchSimulator};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::Path;
+
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_SIMPLE_PACKET: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+// Incomplete IP datagrams kept waiting for their remaining fragments
+const MAX_PENDING_FRAGMENTS: usize = 1024;
+
+// Largest record length believed before allocating; libpcap's maximum snap length
+const MAX_RECORD_LEN: usize = 256 * 1024;
+// Largest pcapng block, options and padding included
+const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+/// Which UDP packets to extract from a capture; empty lists match everything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PacketFilter {
+    pub groups: Vec<Ipv4Addr>,
+    pub ports: Vec<u16>,
+}
+
+impl PacketFilter {
+    pub fn matches(&self, destination: &SocketAddrV4) -> bool {
+        (self.groups.is_empty() || self.groups.contains(destination.ip()))
+            && (self.ports.is_empty() || self.ports.contains(&destination.port()))
+    }
+}
+
+/// A UDP payload extracted from a capture with its capture timestamp
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket {
+    pub timestamp: DateTime<Utc>,
+    pub source: SocketAddrV4,
+    pub destination: SocketAddrV4,
+    pub payload: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Parse the payload as a single Sequenced Unit frame
+    pub fn parse(&self, parser: &PitchParser) -> Result<(SequencedUnitHeader, Vec<PitchMessage>)> {
+        parser.parse_frame(&self.payload)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CaptureStats {
+    pub records: u64,
+    pub packets: u64,          // UDP packets returned to the caller
+    pub filtered: u64,         // UDP packets not matching the filter
+    pub non_udp: u64,          // Non-IPv4 or non-UDP records
+    pub truncated: u64,        // Records cut short by the snap length or end of file
+    pub corrupt: u64,          // Records with an impossible length; reading stops there
+    pub fragments: u64,        // IP fragments seen
+    pub reassembled: u64,      // Datagrams rebuilt from fragments
+    pub dropped_fragments: u64, // Fragmented datagrams that never completed
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptureFormat {
+    Pcap,
+    PcapNg,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u32,
+    units_per_second: u64, // Timestamp resolution
+}
+
+#[derive(Debug)]
+struct Record {
+    interface: usize,
+    timestamp: u64, // In the interface's timestamp units
+    captured_len: usize,
+    original_len: usize,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FragmentKey {
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    id: u16,
+}
+
+#[derive(Debug, Default)]
+struct FragmentBuffer {
+    data: Vec<u8>,
+    received: Vec<(usize, usize)>, // (offset, len) of each fragment
+    total_len: Option<usize>,      // Known once the last fragment arrives
+    first_seen: u64,               // Record index, for eviction
+}
+
+impl FragmentBuffer {
+    fn insert(&mut self, offset: usize, payload: &[u8], last: bool) {
+        let end = offset + payload.len();
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        self.data[offset..end].copy_from_slice(payload);
+        self.received.push((offset, payload.len()));
+        if last {
+            self.total_len = Some(end);
+        }
+    }
+    
+    fn is_complete(&mut self) -> bool {
+        let Some(total_len) = self.total_len else {
+            return false;
+        };
+        
+        self.received.sort_unstable();
+        let mut covered = 0;
+        for &(offset, len) in &self.received {
+            if offset > covered {
+                return false;
+            }
+            covered = covered.max(offset + len);
+        }
+        
+        covered >= total_len
+    }
+}
+
+/// Streams UDP payloads out of pcap or pcapng captures
+pub struct PcapReader<R: Read> {
+    reader: R,
+    format: CaptureFormat,
+    big_endian: bool,
+    interfaces: Vec<Interface>,
+    filter: PacketFilter,
+    fragments: HashMap<FragmentKey, FragmentBuffer>,
+    stats: CaptureStats,
+    parser: PitchParser,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        
+        let mut capture = Self {
+            reader,
+            format: CaptureFormat::Pcap,
+            big_endian: false,
+            interfaces: Vec::new(),
+            filter: PacketFilter::default(),
+            fragments: HashMap::new(),
+            stats: CaptureStats::default(),
+            parser: PitchParser::new(),
+        };
+        
+        if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            capture.format = CaptureFormat::PcapNg;
+            capture.read_section_header()?;
+        } else {
+            capture.read_pcap_header(magic)?;
+        }
+        
+        Ok(capture)
+    }
+    
+    pub fn with_filter(mut self, filter: PacketFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+    
+    pub fn format(&self) -> CaptureFormat {
+        self.format
+    }
+    
+    pub fn stats(&self) -> &CaptureStats {
+        &self.stats
+    }
+    
+    /// Next UDP payload matching the filter, or `None` at end of capture
+    pub fn next_packet(&mut self) -> Result<Option<CapturedPacket>> {
+        while let Some(record) = self.next_record()? {
+            self.stats.records += 1;
+            
+            if record.captured_len < record.original_len {
+                self.stats.truncated += 1;
+                continue;
+            }
+            
+            let Some(interface) = self.interfaces.get(record.interface).copied() else {
+                return Err(PitchError::Parse(format!("Packet references unknown interface {}", record.interface)));
+            };
+            
+            if let Some(packet) = self.decode_record(&record, interface) {
+                self.stats.packets += 1;
+                return Ok(Some(packet));
+            }
+        }
+        
+        self.stats.dropped_fragments += self.fragments.len() as u64;
+        self.fragments.clear();
+        Ok(None)
+    }
+    
+    /// Next matching packet parsed as a Sequenced Unit frame
+    pub fn next_parsed_frame(&mut self) -> Result<Option<(CapturedPacket, SequencedUnitHeader, Vec<PitchMessage>)>> {
+        match self.next_packet()? {
+            Some(packet) => {
+                let (header, messages) = self.parser.parse_frame(&packet.payload)?;
+                Ok(Some((packet, header, messages)))
+            },
+            None => Ok(None),
+        }
+    }
+    
+    fn read_pcap_header(&mut self, magic: [u8; 4]) -> Result<()> {
+        let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC_MICROS, _) => (false, false),
+            (PCAP_MAGIC_NANOS, _) => (false, true),
+            (_, PCAP_MAGIC_MICROS) => (true, false),
+            (_, PCAP_MAGIC_NANOS) => (true, true),
+            _ => return Err(PitchError::Parse(format!("Not a pcap or pcapng file (magic {:02X?})", magic))),
+        };
+        
+        let mut header = [0u8; 20];
+        self.reader.read_exact(&mut header)?;
+        self.big_endian = big_endian;
+        
+        self.interfaces.push(Interface {
+            link_type: self.u32_at(&header, 16) & 0xFFFF,
+            units_per_second: if nanos { 1_000_000_000 } else { 1_000_000 },
+        });
+        
+        Ok(())
+    }
+    
+    fn read_section_header(&mut self) -> Result<()> {
+        let mut fixed = [0u8; 8];
+        self.reader.read_exact(&mut fixed)?;
+        
+        self.big_endian = match u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]) {
+            PCAPNG_BYTE_ORDER_MAGIC => false,
+            _ if u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]) == PCAPNG_BYTE_ORDER_MAGIC => true,
+            _ => return Err(PitchError::Parse("Invalid pcapng byte-order magic".to_string())),
+        };
+        
+        // Interface IDs are scoped to their section
+        self.interfaces.clear();
+        
+        let total_len = self.u32_at(&fixed, 0) as usize;
+        self.skip_exact(total_len.saturating_sub(12))
+    }
+    
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        match self.format {
+            CaptureFormat::Pcap => self.next_pcap_record(),
+            CaptureFormat::PcapNg => self.next_pcapng_record(),
+        }
+    }
+    
+    fn next_pcap_record(&mut self) -> Result<Option<Record>> {
+        let mut header = [0u8; 16];
+        if !self.read_or_eof(&mut header)? {
+            return Ok(None);
+        }
+        
+        let seconds = self.u32_at(&header, 0) as u64;
+        let fraction = self.u32_at(&header, 4) as u64;
+        let captured_len = self.u32_at(&header, 8) as usize;
+        let original_len = self.u32_at(&header, 12) as usize;
+        
+        // Nothing after a bogus length can be framed, so treat it as the end of the capture
+        if captured_len > MAX_RECORD_LEN {
+            self.stats.corrupt += 1;
+            return Ok(None);
+        }
+        
+        let mut data = vec![0u8; captured_len];
+        if !self.read_or_eof(&mut data)? {
+            self.stats.truncated += 1;
+            return Ok(None);
+        }
+        
+        let units_per_second = self.interfaces[0].units_per_second;
+        
+        Ok(Some(Record {
+            interface: 0,
+            timestamp: seconds * units_per_second + fraction,
+            captured_len,
+            original_len,
+            data,
+        }))
+    }
+    
+    fn next_pcapng_record(&mut self) -> Result<Option<Record>> {
+        loop {
+            let mut block_header = [0u8; 8];
+            if !self.read_or_eof(&mut block_header)? {
+                return Ok(None);
+            }
+            
+            if u32::from_le_bytes([block_header[0], block_header[1], block_header[2], block_header[3]]) == PCAPNG_SECTION_HEADER {
+                let mut magic = [0u8; 4];
+                self.reader.read_exact(&mut magic)?;
+                self.big_endian = u32::from_le_bytes(magic) != PCAPNG_BYTE_ORDER_MAGIC;
+                self.interfaces.clear();
+                
+                let total_len = self.u32_at(&block_header, 4) as usize;
+                self.skip_exact(total_len.saturating_sub(12))?;
+                continue;
+            }
+            
+            let block_type = self.u32_at(&block_header, 0);
+            let total_len = self.u32_at(&block_header, 4) as usize;
+            if total_len < 12 {
+                return Err(PitchError::Parse(format!("Invalid pcapng block length {}", total_len)));
+            }
+            if total_len > MAX_BLOCK_LEN {
+                self.stats.corrupt += 1;
+                return Ok(None);
+            }
+            
+            // Body plus the trailing copy of the block length
+            let mut body = vec![0u8; total_len - 8];
+            if !self.read_or_eof(&mut body)? {
+                self.stats.truncated += 1;
+                return Ok(None);
+            }
+            let body = &body[..total_len - 12];
+            
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION if body.len() >= 8 => {
+                    let link_type = self.u16_at(body, 0) as u32;
+                    let units_per_second = self.interface_resolution(&body[8..]);
+                    self.interfaces.push(Interface { link_type, units_per_second });
+                },
+                PCAPNG_ENHANCED_PACKET if body.len() >= 20 => {
+                    let interface = self.u32_at(body, 0) as usize;
+                    let timestamp = ((self.u32_at(body, 4) as u64) << 32) | self.u32_at(body, 8) as u64;
+                    let captured_len = self.u32_at(body, 12) as usize;
+                    let original_len = self.u32_at(body, 16) as usize;
+                    let available = body.len() - 20;
+                    
+                    return Ok(Some(Record {
+                        interface,
+                        timestamp,
+                        captured_len: captured_len.min(available),
+                        original_len,
+                        data: body[20..20 + captured_len.min(available)].to_vec(),
+                    }));
+                },
+                PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+                    // Simple packets carry no timestamp and always use interface 0
+                    let original_len = self.u32_at(body, 0) as usize;
+                    let captured_len = original_len.min(body.len() - 4);
+                    
+                    return Ok(Some(Record {
+                        interface: 0,
+                        timestamp: 0,
+                        captured_len,
+                        original_len,
+                        data: body[4..4 + captured_len].to_vec(),
+                    }));
+                },
+                _ => {}
+            }
+        }
+    }
+    
+    fn interface_resolution(&self, mut options: &[u8]) -> u64 {
+        const OPTION_END: u16 = 0;
+        const OPTION_TSRESOL: u16 = 9;
+        
+        while options.len() >= 4 {
+            let code = self.u16_at(options, 0);
+            let len = self.u16_at(options, 2) as usize;
+            if code == OPTION_END || options.len() < 4 + len {
+                break;
+            }
+            
+            if code == OPTION_TSRESOL && len >= 1 {
+                let resolution = options[4];
+                let exponent = (resolution & 0x7F) as u32;
+                return if resolution & 0x80 == 0 {
+                    10u64.checked_pow(exponent).unwrap_or(1_000_000)
+                } else {
+                    2u64.checked_pow(exponent).unwrap_or(1_000_000)
+                };
+            }
+            
+            options = options.get(4 + len.div_ceil(4) * 4..).unwrap_or(&[]);
+        }
+        
+        1_000_000
+    }
+    
+    fn decode_record(&mut self, record: &Record, interface: Interface) -> Option<CapturedPacket> {
+        let Some(ip) = Self::ipv4_payload(interface.link_type, &record.data) else {
+            self.stats.non_udp += 1;
+            return None;
+        };
+        
+        if ip.len() < 20 || ip[0] >> 4 != 4 {
+            self.stats.non_udp += 1;
+            return None;
+        }
+        
+        let header_len = ((ip[0] & 0x0F) as usize) * 4;
+        let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+        if header_len < 20 || total_len < header_len || ip.len() < total_len {
+            self.stats.truncated += 1;
+            return None;
+        }
+        
+        if ip[9] != IP_PROTOCOL_UDP {
+            self.stats.non_udp += 1;
+            return None;
+        }
+        
+        let source = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+        let destination = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+        let flags_offset = u16::from_be_bytes([ip[6], ip[7]]);
+        let more_fragments = flags_offset & 0x2000 != 0;
+        let fragment_offset = ((flags_offset & 0x1FFF) as usize) * 8;
+        let payload = &ip[header_len..total_len];
+        
+        let udp = if more_fragments || fragment_offset > 0 {
+            self.stats.fragments += 1;
+            let key = FragmentKey {
+                source,
+                destination,
+                id: u16::from_be_bytes([ip[4], ip[5]]),
+            };
+            self.reassemble(key, fragment_offset, payload, !more_fragments)?
+        } else {
+            payload.to_vec()
+        };
+        
+        if udp.len() < 8 {
+            self.stats.truncated += 1;
+            return None;
+        }
+        
+        let source_port = u16::from_be_bytes([udp[0], udp[1]]);
+        let destination_port = u16::from_be_bytes([udp[2], udp[3]]);
+        let udp_len = u16::from_be_bytes([udp[4], udp[5]]) as usize;
+        if udp_len < 8 || udp_len > udp.len() {
+            self.stats.truncated += 1;
+            return None;
+        }
+        
+        let destination = SocketAddrV4::new(destination, destination_port);
+        if !self.filter.matches(&destination) {
+            self.stats.filtered += 1;
+            return None;
+        }
+        
+        let timestamp = interface.timestamp_to_datetime(record.timestamp);
+        
+        Some(CapturedPacket {
+            timestamp,
+            source: SocketAddrV4::new(source, source_port),
+            destination,
+            payload: udp[8..udp_len].to_vec(),
+        })
+    }
+    
+    fn reassemble(&mut self, key: FragmentKey, offset: usize, payload: &[u8], last: bool) -> Option<Vec<u8>> {
+        if !self.fragments.contains_key(&key) && self.fragments.len() >= MAX_PENDING_FRAGMENTS {
+            // Evict the oldest incomplete datagram
+            if let Some(oldest) = self.fragments.iter().min_by_key(|(_, buffer)| buffer.first_seen).map(|(&key, _)| key) {
+                self.fragments.remove(&oldest);
+                self.stats.dropped_fragments += 1;
+            }
+        }
+        
+        let records = self.stats.records;
+        let buffer = self.fragments.entry(key).or_insert_with(|| FragmentBuffer {
+            first_seen: records,
+            ..FragmentBuffer::default()
+        });
+        buffer.insert(offset, payload, last);
+        
+        if !buffer.is_complete() {
+            return None;
+        }
+        
+        self.stats.reassembled += 1;
+        self.fragments.remove(&key).map(|buffer| buffer.data)
+    }
+    
+    fn ipv4_payload(link_type: u32, data: &[u8]) -> Option<&[u8]> {
+        match link_type {
+            LINKTYPE_ETHERNET => {
+                let mut offset = 12;
+                loop {
+                    let ether_type = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]);
+                    match ether_type {
+                        ETHERTYPE_VLAN | ETHERTYPE_QINQ => offset += 4,
+                        ETHERTYPE_IPV4 => return data.get(offset + 2..),
+                        _ => return None,
+                    }
+                }
+            },
+            LINKTYPE_LINUX_SLL => {
+                let protocol = u16::from_be_bytes([*data.get(14)?, *data.get(15)?]);
+                if protocol == ETHERTYPE_IPV4 {
+                    data.get(16..)
+                } else {
+                    None
+                }
+            },
+            LINKTYPE_NULL => {
+                // Host byte order of the capturing machine; AF_INET is 2 everywhere
+                let family = *data.first()? as u32 | *data.get(3)? as u32;
+                if family == 2 {
+                    data.get(4..)
+                } else {
+                    None
+                }
+            },
+            LINKTYPE_RAW | LINKTYPE_IPV4 => Some(data),
+            _ => None,
+        }
+    }
+    
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    fn skip_exact(&mut self, len: usize) -> Result<()> {
+        let copied = std::io::copy(&mut (&mut self.reader).take(len as u64), &mut std::io::sink())?;
+        if copied < len as u64 {
+            return Err(PitchError::InsufficientData {
+                expected: len,
+                actual: copied as usize,
+            });
+        }
+        Ok(())
+    }
+    
+    fn u16_at(&self, data: &[u8], offset: usize) -> u16 {
+        let bytes = [data[offset], data[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+    
+    fn u32_at(&self, data: &[u8], offset: usize) -> u32 {
+        let bytes = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl Interface {
+    fn timestamp_to_datetime(&self, timestamp: u64) -> DateTime<Utc> {
+        let seconds = timestamp / self.units_per_second;
+        let fraction = timestamp % self.units_per_second;
+        let nanos = (fraction as u128 * 1_000_000_000 / self.units_per_second as u128) as u32;
+        
+        DateTime::from_timestamp(seconds as i64, nanos).unwrap_or_default()
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<CapturedPacket>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+/// Addressing used when wrapping frames in synthetic Ethernet/IPv4/UDP headers
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcapWriterConfig {
+    pub source: SocketAddrV4,
+    pub source_mac: [u8; 6],
+    pub default_destination: SocketAddrV4,
+    pub unit_destinations: HashMap<u8, SocketAddrV4>, // Per-unit multicast group and port
+    pub ttl: u8,
+}
+
+impl PcapWriterConfig {
+    pub fn with_unit(mut self, unit: u8, destination: SocketAddrV4) -> Self {
+        self.unit_destinations.insert(unit, destination);
+        self
+    }
+    
+    pub fn destination_for(&self, unit: u8) -> SocketAddrV4 {
+        self.unit_destinations.get(&unit).copied().unwrap_or(self.default_destination)
+    }
+}
+
+impl Default for PcapWriterConfig {
+    fn default() -> Self {
+        Self {
+            source: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 30501),
+            source_mac: [0x02, 0x00, 0x00, 0x00, 0x00, 0x01], // Locally administered
+            default_destination: SocketAddrV4::new(Ipv4Addr::new(233, 218, 133, 80), 30501),
+            unit_destinations: HashMap::new(),
+            ttl: 32,
+        }
+    }
+}
+
+/// Writes Sequenced Unit frames as a nanosecond-resolution Ethernet pcap
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    config: PcapWriterConfig,
+    ip_id: u16,
+    last_timestamp: Option<DateTime<Utc>>,
+    packets: u64,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, config: PcapWriterConfig) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), config)
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut writer: W, config: PcapWriterConfig) -> Result<Self> {
+        writer.write_all(&PCAP_MAGIC_NANOS.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // Version 2.4
+        writer.write_all(&4u16.to_le_bytes())?;
+        writer.write_all(&0i32.to_le_bytes())?; // GMT offset
+        writer.write_all(&0u32.to_le_bytes())?; // Timestamp accuracy
+        writer.write_all(&(MAX_RECORD_LEN as u32).to_le_bytes())?; // Snap length
+        writer.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+        
+        Ok(Self {
+            writer,
+            config,
+            ip_id: 0,
+            last_timestamp: None,
+            packets: 0,
+        })
+    }
+    
+    /// Write one serialized frame to the multicast group configured for its unit
+    pub fn write_frame(&mut self, timestamp: DateTime<Utc>, frame: &[u8]) -> Result<()> {
+        let header = PitchParser::read_header(frame)?;
+        let destination = self.config.destination_for(header.unit);
+        self.write_packet(timestamp, self.config.source, destination, frame)
+    }
+    
+    /// Write simulator output, stamping each packet with its first message's exchange time
+    pub fn write_simulated(&mut self, simulator: &PitchSimulator, frames: &[(SequencedUnitHeader, Vec<PitchMessage>)]) -> Result<()> {
+        for (header, messages) in frames {
+            let timestamp = messages
+                .first()
+                .map(|message| message.timestamp())
+                .or(self.last_timestamp)
+                .unwrap_or_else(Utc::now);
+            let frame = simulator.serialize_frame(header, messages)?;
+            self.write_frame(timestamp, &frame)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Re-emit a packet from a capture, keeping its original addressing and timestamp
+    pub fn write_captured(&mut self, packet: &CapturedPacket) -> Result<()> {
+        self.write_packet(packet.timestamp, packet.source, packet.destination, &packet.payload)
+    }
+    
+    pub fn write_packet(&mut self, timestamp: DateTime<Utc>, source: SocketAddrV4, destination: SocketAddrV4, payload: &[u8]) -> Result<()> {
+        let ip_len = IPV4_HEADER_LEN + UDP_HEADER_LEN + payload.len();
+        if ip_len > u16::MAX as usize {
+            return Err(PitchError::Parse(format!("UDP payload of {} bytes does not fit in one datagram", payload.len())));
+        }
+        
+        let mut packet = Vec::with_capacity(ETHERNET_HEADER_LEN + ip_len);
+        
+        // Ethernet: IPv4 multicast maps the low 23 bits of the group into 01:00:5E
+        let group = destination.ip().octets();
+        let destination_mac = if destination.ip().is_multicast() {
+            [0x01, 0x00, 0x5E, group[1] & 0x7F, group[2], group[3]]
+        } else {
+            [0xFF; 6]
+        };
+        packet.extend_from_slice(&destination_mac);
+        packet.extend_from_slice(&self.config.source_mac);
+        packet.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        
+        // IPv4, don't-fragment set
+        let ip_start = packet.len();
+        packet.push(0x45);
+        packet.push(0);
+        packet.extend_from_slice(&(ip_len as u16).to_be_bytes());
+        packet.extend_from_slice(&self.ip_id.to_be_bytes());
+        packet.extend_from_slice(&0x4000u16.to_be_bytes());
+        packet.push(self.config.ttl);
+        packet.push(IP_PROTOCOL_UDP);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&source.ip().octets());
+        packet.extend_from_slice(&group);
+        let checksum = ipv4_checksum(&packet[ip_start..]);
+        packet[ip_start + 10..ip_start + 12].copy_from_slice(&checksum.to_be_bytes());
+        self.ip_id = self.ip_id.wrapping_add(1);
+        
+        // UDP, checksum left at zero (optional over IPv4)
+        packet.extend_from_slice(&source.port().to_be_bytes());
+        packet.extend_from_slice(&destination.port().to_be_bytes());
+        packet.extend_from_slice(&((UDP_HEADER_LEN + payload.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(payload);
+        
+        let nanos = timestamp.timestamp_nanos_opt().unwrap_or(0).max(0) as u64;
+        self.writer.write_all(&((nanos / 1_000_000_000) as u32).to_le_bytes())?;
+        self.writer.write_all(&((nanos % 1_000_000_000) as u32).to_le_bytes())?;
+        self.writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&packet)?;
+        
+        self.last_timestamp = Some(timestamp);
+        self.packets += 1;
+        Ok(())
+    }
+    
+    pub fn packets_written(&self) -> u64 {
+        self.packets
+    }
+    
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+    
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    
+    const SOURCE: [u8; 4] = [10, 0, 0, 1];
+    const GROUP: [u8; 4] = [233, 218, 133, 80];
+    
+    struct Bytes {
+        data: Vec<u8>,
+        big_endian: bool,
+    }
+    
+    impl Bytes {
+        fn new(big_endian: bool) -> Self {
+            Self { data: Vec::new(), big_endian }
+        }
+        
+        fn u16(&mut self, value: u16) -> &mut Self {
+            let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+            self.data.extend_from_slice(&bytes);
+            self
+        }
+        
+        fn u32(&mut self, value: u32) -> &mut Self {
+            let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+            self.data.extend_from_slice(&bytes);
+            self
+        }
+        
+        fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+            self.data.extend_from_slice(bytes);
+            self
+        }
+    }
+    
+    fn udp(payload: &[u8]) -> Vec<u8> {
+        let mut udp = Vec::new();
+        udp.extend_from_slice(&30501u16.to_be_bytes());
+        udp.extend_from_slice(&30502u16.to_be_bytes());
+        udp.extend_from_slice(&((UDP_HEADER_LEN + payload.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(payload);
+        udp
+    }
+    
+    fn ipv4(id: u16, flags_offset: u16, payload: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0];
+        ip.extend_from_slice(&((IPV4_HEADER_LEN + payload.len()) as u16).to_be_bytes());
+        ip.extend_from_slice(&id.to_be_bytes());
+        ip.extend_from_slice(&flags_offset.to_be_bytes());
+        ip.extend_from_slice(&[32, IP_PROTOCOL_UDP, 0, 0]);
+        ip.extend_from_slice(&SOURCE);
+        ip.extend_from_slice(&GROUP);
+        ip.extend_from_slice(payload);
+        ip
+    }
+    
+    fn ethernet(tags: &[u16], ip: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x01, 0x00, 0x5E, 0x5A, 0x85, 0x50, 0x02, 0, 0, 0, 0, 1];
+        for &tag in tags {
+            frame.extend_from_slice(&tag.to_be_bytes());
+            frame.extend_from_slice(&100u16.to_be_bytes()); // VLAN ID
+        }
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(ip);
+        frame
+    }
+    
+    fn packet(payload: &[u8]) -> Vec<u8> {
+        ethernet(&[], &ipv4(1, 0x4000, &udp(payload)))
+    }
+    
+    fn pcap(big_endian: bool, nanos: bool) -> Bytes {
+        let mut bytes = Bytes::new(big_endian);
+        bytes
+            .u32(if nanos { PCAP_MAGIC_NANOS } else { PCAP_MAGIC_MICROS })
+            .u16(2)
+            .u16(4)
+            .u32(0)
+            .u32(0)
+            .u32(MAX_RECORD_LEN as u32)
+            .u32(LINKTYPE_ETHERNET);
+        bytes
+    }
+    
+    fn record(bytes: &mut Bytes, seconds: u32, fraction: u32, data: &[u8]) {
+        bytes.u32(seconds).u32(fraction).u32(data.len() as u32).u32(data.len() as u32).raw(data);
+    }
+    
+    fn block(bytes: &mut Bytes, block_type: u32, body: &[u8]) {
+        let padded = body.len().div_ceil(4) * 4;
+        let total_len = (12 + padded) as u32;
+        bytes.u32(block_type).u32(total_len).raw(body).raw(&vec![0; padded - body.len()]).u32(total_len);
+    }
+    
+    fn pcapng(big_endian: bool, resolutions: &[Option<u8>]) -> Bytes {
+        let mut bytes = Bytes::new(big_endian);
+        let mut section = Bytes::new(big_endian);
+        section.u32(PCAPNG_BYTE_ORDER_MAGIC).u16(1).u16(0).u32(u32::MAX).u32(u32::MAX);
+        // The section header's type is a palindrome, so it reads the same in either byte order
+        block(&mut bytes, PCAPNG_SECTION_HEADER, &section.data);
+        
+        for resolution in resolutions {
+            let mut interface = Bytes::new(big_endian);
+            interface.u16(LINKTYPE_ETHERNET as u16).u16(0).u32(MAX_RECORD_LEN as u32);
+            if let Some(resolution) = resolution {
+                interface.u16(9).u16(1).raw(&[*resolution, 0, 0, 0]).u16(0).u16(0);
+            }
+            block(&mut bytes, PCAPNG_INTERFACE_DESCRIPTION, &interface.data);
+        }
+        
+        bytes
+    }
+    
+    fn enhanced_packet(bytes: &mut Bytes, interface: u32, timestamp: u64, data: &[u8]) {
+        let mut body = Bytes::new(bytes.big_endian);
+        body.u32(interface)
+            .u32((timestamp >> 32) as u32)
+            .u32(timestamp as u32)
+            .u32(data.len() as u32)
+            .u32(data.len() as u32)
+            .raw(data);
+        block(bytes, PCAPNG_ENHANCED_PACKET, &body.data);
+    }
+    
+    fn reader(bytes: Bytes) -> PcapReader<Cursor<Vec<u8>>> {
+        PcapReader::new(Cursor::new(bytes.data)).unwrap()
+    }
+    
+    fn packets(reader: &mut PcapReader<Cursor<Vec<u8>>>) -> Vec<CapturedPacket> {
+        reader.collect::<Result<Vec<_>>>().unwrap()
+    }
+    
+    #[test]
+    fn classic_pcap_timestamps_in_either_resolution_and_byte_order() {
+        for big_endian in [false, true] {
+            for nanos in [false, true] {
+                let fraction = if nanos { 123_456_789 } else { 123_456 };
+                let mut bytes = pcap(big_endian, nanos);
+                record(&mut bytes, 1_700_000_000, fraction, &packet(b"frame"));
+                
+                let mut reader = reader(bytes);
+                assert_eq!(reader.format(), CaptureFormat::Pcap);
+                
+                let packet = reader.next_packet().unwrap().unwrap();
+                let expected_nanos = if nanos { 123_456_789 } else { 123_456_000 };
+                assert_eq!(packet.timestamp, DateTime::from_timestamp(1_700_000_000, expected_nanos).unwrap());
+                assert_eq!(packet.source, SocketAddrV4::new(SOURCE.into(), 30501));
+                assert_eq!(packet.destination, SocketAddrV4::new(GROUP.into(), 30502));
+                assert_eq!(packet.payload, b"frame");
+                assert!(reader.next_packet().unwrap().is_none());
+            }
+        }
+    }
+    
+    #[test]
+    fn pcapng_uses_each_interfaces_timestamp_resolution() {
+        for big_endian in [false, true] {
+            // Nanoseconds, the microsecond default, and 2^-10 second units
+            let mut bytes = pcapng(big_endian, &[Some(9), None, Some(0x80 | 10)]);
+            enhanced_packet(&mut bytes, 0, 1_700_000_000_123_456_789, &packet(b"ns"));
+            enhanced_packet(&mut bytes, 1, 1_700_000_000_123_456, &packet(b"us"));
+            enhanced_packet(&mut bytes, 2, 1_700_000_000 * 1024 + 512, &packet(b"pow2"));
+            
+            let mut reader = reader(bytes);
+            assert_eq!(reader.format(), CaptureFormat::PcapNg);
+            
+            let packets = packets(&mut reader);
+            assert_eq!(packets.len(), 3);
+            assert_eq!(packets[0].payload, b"ns");
+            assert_eq!(packets[0].timestamp, DateTime::from_timestamp(1_700_000_000, 123_456_789).unwrap());
+            assert_eq!(packets[1].payload, b"us");
+            assert_eq!(packets[1].timestamp, DateTime::from_timestamp(1_700_000_000, 123_456_000).unwrap());
+            assert_eq!(packets[2].payload, b"pow2");
+            assert_eq!(packets[2].timestamp, DateTime::from_timestamp(1_700_000_000, 500_000_000).unwrap());
+        }
+    }
+    
+    #[test]
+    fn pcapng_packet_for_unknown_interface_is_an_error() {
+        let mut bytes = pcapng(false, &[None]);
+        enhanced_packet(&mut bytes, 3, 0, &packet(b"frame"));
+        
+        assert!(reader(bytes).next_packet().is_err());
+    }
+    
+    #[test]
+    fn vlan_and_qinq_tags_are_stripped() {
+        let ip = ipv4(1, 0x4000, &udp(b"frame"));
+        let mut bytes = pcap(false, true);
+        record(&mut bytes, 1_700_000_000, 0, &ethernet(&[ETHERTYPE_VLAN], &ip));
+        record(&mut bytes, 1_700_000_001, 0, &ethernet(&[ETHERTYPE_QINQ, ETHERTYPE_VLAN], &ip));
+        record(&mut bytes, 1_700_000_002, 0, &ethernet(&[ETHERTYPE_VLAN], &[0x60; 40])); // IPv6 behind a tag
+        
+        let mut reader = reader(bytes);
+        let packets = packets(&mut reader);
+        
+        assert_eq!(packets.len(), 2);
+        assert!(packets.iter().all(|packet| packet.payload == b"frame"));
+        assert_eq!(reader.stats().non_udp, 1);
+    }
+    
+    #[test]
+    fn fragments_are_reassembled_in_any_order() {
+        let datagram = udp(&[7u8; 32]);
+        let mut bytes = pcap(false, true);
+        // Offsets are in 8-byte units; 24 bytes fit in the first fragment
+        record(&mut bytes, 1_700_000_000, 0, &ethernet(&[], &ipv4(9, 3, &datagram[24..])));
+        record(&mut bytes, 1_700_000_000, 1, &ethernet(&[], &ipv4(9, 0x2000, &datagram[..24])));
+        
+        let mut reader = reader(bytes);
+        let packets = packets(&mut reader);
+        
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].payload, vec![7u8; 32]);
+        assert_eq!(reader.stats().fragments, 2);
+        assert_eq!(reader.stats().reassembled, 1);
+        assert_eq!(reader.stats().dropped_fragments, 0);
+    }
+    
+    #[test]
+    fn oldest_incomplete_datagram_is_evicted() {
+        let datagram = udp(&[7u8; 32]);
+        let mut bytes = pcap(false, true);
+        for id in 0..=MAX_PENDING_FRAGMENTS as u16 {
+            record(&mut bytes, 1_700_000_000, id as u32, &ethernet(&[], &ipv4(id, 0x2000, &datagram[..24])));
+        }
+        // Datagram 0 was evicted, so its tail starts a new entry (evicting datagram 1); the newest still completes
+        record(&mut bytes, 1_700_000_001, 0, &ethernet(&[], &ipv4(0, 3, &datagram[24..])));
+        record(&mut bytes, 1_700_000_001, 1, &ethernet(&[], &ipv4(MAX_PENDING_FRAGMENTS as u16, 3, &datagram[24..])));
+        
+        let mut reader = reader(bytes);
+        let packet = reader.next_packet().unwrap().unwrap();
+        
+        assert_eq!(packet.payload, vec![7u8; 32]);
+        assert_eq!(reader.stats().reassembled, 1);
+        assert_eq!(reader.stats().dropped_fragments, 2);
+        
+        // Whatever is still pending at the end of the capture is dropped too
+        assert!(reader.next_packet().unwrap().is_none());
+        assert_eq!(reader.stats().dropped_fragments, 1 + MAX_PENDING_FRAGMENTS as u64); // 2 evicted, 1023 left pending
+    }
+    
+    #[test]
+    fn truncated_records_are_counted_and_skipped() {
+        let data = packet(b"frame");
+        let mut bytes = pcap(false, true);
+        // Cut short by the snap length
+        bytes.u32(1_700_000_000).u32(0).u32(20).u32(data.len() as u32).raw(&data[..20]);
+        record(&mut bytes, 1_700_000_001, 0, &data);
+        // Cut short by the end of the file
+        bytes.u32(1_700_000_002).u32(0).u32(data.len() as u32).u32(data.len() as u32).raw(&data[..10]);
+        
+        let mut reader = reader(bytes);
+        let packets = packets(&mut reader);
+        
+        assert_eq!(packets.len(), 1);
+        assert_eq!(reader.stats().truncated, 2);
+        assert_eq!(reader.stats().corrupt, 0);
+    }
+    
+    #[test]
+    fn oversized_record_length_marks_capture_corrupt() {
+        let mut bytes = pcap(false, true);
+        record(&mut bytes, 1_700_000_000, 0, &packet(b"frame"));
+        bytes.u32(1_700_000_001).u32(0).u32(MAX_RECORD_LEN as u32 + 1).u32(MAX_RECORD_LEN as u32 + 1);
+        record(&mut bytes, 1_700_000_002, 0, &packet(b"never read"));
+        
+        let mut reader = reader(bytes);
+        let packets = packets(&mut reader);
+        
+        assert_eq!(packets.len(), 1);
+        assert_eq!(reader.stats().corrupt, 1);
+        assert_eq!(reader.stats().records, 1);
+    }
+    
+    #[test]
+    fn oversized_pcapng_block_marks_capture_corrupt() {
+        let mut bytes = pcapng(false, &[None]);
+        enhanced_packet(&mut bytes, 0, 0, &packet(b"frame"));
+        bytes.u32(PCAPNG_ENHANCED_PACKET).u32(MAX_BLOCK_LEN as u32 + 4);
+        
+        let mut reader = reader(bytes);
+        let packets = packets(&mut reader);
+        
+        assert_eq!(packets.len(), 1);
+        assert_eq!(reader.stats().corrupt, 1);
+    }
+    
+    #[test]
+    fn writer_output_reads_back() {
+        let mut writer = PcapWriter::new(Vec::new(), PcapWriterConfig::default()).unwrap();
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 987_654_321).unwrap();
+        let destination = SocketAddrV4::new(GROUP.into(), 30502);
+        writer.write_packet(timestamp, SocketAddrV4::new(SOURCE.into(), 30501), destination, b"frame").unwrap();
+        
+        let mut reader = PcapReader::new(Cursor::new(writer.into_inner().unwrap())).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        
+        assert_eq!(packet.timestamp, timestamp);
+        assert_eq!(packet.destination, destination);
+        assert_eq!(packet.payload, b"frame");
+    }
+}
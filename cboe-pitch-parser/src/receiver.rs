@@ -0,0 +1,366 @@
+use crate::{error::*, message::*, parser::PitchParser};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Multicast feeds published for each unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Feed {
+    A, // Primary DC, primary
+    B, // Primary DC, secondary
+    E, // Secondary DC, disaster recovery
+}
+
+/// One multicast group/port to join
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelConfig {
+    pub feed: Feed,
+    pub group: Ipv4Addr,
+    pub port: u16,
+    pub interface: Ipv4Addr,
+    pub units: Vec<u8>, // Units to accept on this channel, empty = all
+}
+
+impl ChannelConfig {
+    pub fn new(feed: Feed, group: Ipv4Addr, port: u16) -> Self {
+        Self {
+            feed,
+            group,
+            port,
+            interface: Ipv4Addr::UNSPECIFIED,
+            units: Vec::new(),
+        }
+    }
+    
+    pub fn with_interface(mut self, interface: Ipv4Addr) -> Self {
+        self.interface = interface;
+        self
+    }
+    
+    pub fn with_units(mut self, units: &[u8]) -> Self {
+        self.units = units.to_vec();
+        self
+    }
+    
+    pub fn accepts_unit(&self, unit: u8) -> bool {
+        self.units.is_empty() || self.units.contains(&unit)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceiverConfig {
+    pub channels: Vec<ChannelConfig>,
+    pub max_datagram_size: usize,
+    pub socket_buffer_size: Option<usize>, // SO_RCVBUF, OS default if None
+}
+
+impl ReceiverConfig {
+    pub fn new(channels: Vec<ChannelConfig>) -> Self {
+        Self {
+            channels,
+            max_datagram_size: 65_536,
+            socket_buffer_size: None,
+        }
+    }
+}
+
+/// Per-channel receive counters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub datagrams: u64,
+    pub bytes: u64,
+    pub frames: u64,
+    pub heartbeats: u64,    // Frames with count = 0
+    pub filtered: u64,      // Frames for units not accepted by the channel
+    pub malformed: u64,     // Datagrams too short for their header
+    pub last_received: Option<DateTime<Utc>>,
+    pub last_sequence: HashMap<u8, u32>, // Unit -> sequence of last frame
+}
+
+/// A Sequenced Unit frame borrowed from the receive buffer
+#[derive(Debug)]
+pub struct ReceivedFrame<'a> {
+    pub channel: usize,
+    pub feed: Feed,
+    pub received_at: DateTime<Utc>,
+    pub header: SequencedUnitHeader,
+    pub data: &'a [u8],
+    parser: &'a PitchParser,
+}
+
+impl ReceivedFrame<'_> {
+    pub fn is_heartbeat(&self) -> bool {
+        self.header.is_heartbeat()
+    }
+    
+    /// Parse the messages directly from the receive buffer
+    pub fn messages(&self) -> Result<Vec<PitchMessage>> {
+        self.parser.parse_frame(self.data).map(|(_, messages)| messages)
+    }
+}
+
+struct Pending {
+    channel: usize,
+    len: usize,
+    received_at: DateTime<Utc>,
+    header: SequencedUnitHeader,
+}
+
+struct Channel {
+    config: ChannelConfig,
+    socket: UdpSocket,
+    stats: ChannelStats,
+}
+
+/// Non-blocking UDP multicast receiver reading one Sequenced Unit frame per datagram
+pub struct MulticastReceiver {
+    channels: Vec<Channel>,
+    buffer: Vec<u8>,
+    parser: PitchParser,
+    next_channel: usize,
+}
+
+impl MulticastReceiver {
+    pub fn bind(config: ReceiverConfig) -> Result<Self> {
+        if config.channels.is_empty() {
+            return Err(PitchError::Config("no multicast channels configured".to_string()));
+        }
+        
+        let mut channels = Vec::with_capacity(config.channels.len());
+        
+        for channel in config.channels {
+            let socket = Self::open_socket(&channel, config.socket_buffer_size)?;
+            channels.push(Channel {
+                config: channel,
+                socket,
+                stats: ChannelStats::default(),
+            });
+        }
+        
+        Ok(Self {
+            channels,
+            buffer: vec![0u8; config.max_datagram_size],
+            parser: PitchParser::new(),
+            next_channel: 0,
+        })
+    }
+    
+    fn open_socket(config: &ChannelConfig, buffer_size: Option<usize>) -> Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        
+        // Feeds A and B usually share a port on different groups
+        socket.set_reuse_address(true)?;
+        
+        if let Some(size) = buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        
+        // Binding to the group keeps other groups on the same port out of this
+        // socket on Unix; Windows only allows binding to the wildcard address
+        let bind_addr = if cfg!(unix) {
+            config.group
+        } else {
+            Ipv4Addr::UNSPECIFIED
+        };
+        
+        socket.bind(&SocketAddrV4::new(bind_addr, config.port).into())?;
+        socket.join_multicast_v4(&config.group, &config.interface)?;
+        socket.set_nonblocking(true)?;
+        
+        Ok(socket.into())
+    }
+    
+    /// Return the next pending frame from any channel, or `None` if all are idle
+    pub fn try_recv(&mut self) -> Result<Option<ReceivedFrame<'_>>> {
+        let pending = self.poll_channels()?;
+        Ok(pending.map(|pending| self.frame(pending)))
+    }
+    
+    /// Busy-poll all channels until a frame arrives or `timeout` elapses
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<ReceivedFrame<'_>>> {
+        let deadline = Instant::now() + timeout;
+        
+        loop {
+            if let Some(pending) = self.poll_channels()? {
+                return Ok(Some(self.frame(pending)));
+            }
+            
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            
+            std::thread::yield_now();
+        }
+    }
+    
+    fn frame(&self, pending: Pending) -> ReceivedFrame<'_> {
+        ReceivedFrame {
+            channel: pending.channel,
+            feed: self.channels[pending.channel].config.feed,
+            received_at: pending.received_at,
+            header: pending.header,
+            data: &self.buffer[..pending.len],
+            parser: &self.parser,
+        }
+    }
+    
+    fn poll_channels(&mut self) -> Result<Option<Pending>> {
+        let channel_count = self.channels.len();
+        
+        for offset in 0..channel_count {
+            let index = (self.next_channel + offset) % channel_count;
+            let channel = &mut self.channels[index];
+            
+            loop {
+                let len = match channel.socket.recv(&mut self.buffer) {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                let received_at = Utc::now();
+                
+                let stats = &mut channel.stats;
+                stats.datagrams += 1;
+                stats.bytes += len as u64;
+                stats.last_received = Some(received_at);
+                
+                let header = match PitchParser::read_header(&self.buffer[..len]) {
+                    Ok(header) if header.length >= 8 && header.length as usize <= len => header,
+                    _ => {
+                        stats.malformed += 1;
+                        continue;
+                    }
+                };
+                
+                if !channel.config.accepts_unit(header.unit) {
+                    stats.filtered += 1;
+                    continue;
+                }
+                
+                stats.frames += 1;
+                if header.is_heartbeat() {
+                    stats.heartbeats += 1;
+                }
+                stats.last_sequence.insert(header.unit, header.sequence);
+                
+                // Start the next poll after this channel so a busy line cannot starve the others
+                self.next_channel = (index + 1) % channel_count;
+                return Ok(Some(Pending {
+                    channel: index,
+                    len: header.length as usize,
+                    received_at,
+                    header,
+                }));
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+    
+    pub fn channel_config(&self, channel: usize) -> Option<&ChannelConfig> {
+        self.channels.get(channel).map(|channel| &channel.config)
+    }
+    
+    pub fn channel_stats(&self, channel: usize) -> Option<&ChannelStats> {
+        self.channels.get(channel).map(|channel| &channel.stats)
+    }
+    
+    pub fn reset_stats(&mut self) {
+        for channel in &mut self.channels {
+            channel.stats = ChannelStats::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::PitchSimulator;
+    use chrono::TimeZone;
+    
+    fn frame(unit: u8, sequence: u32, count: usize) -> Vec<u8> {
+        let messages: Vec<PitchMessage> = (0..count)
+            .map(|i| PitchMessage::DeleteOrder {
+                timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000),
+                order_id: OrderId(i as u64),
+            })
+            .collect();
+        let header = SequencedUnitHeader { length: 0, count: count as u8, unit, sequence };
+        PitchSimulator::new().serialize_frame(&header, &messages).unwrap()
+    }
+    
+    fn free_port() -> u16 {
+        UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+    
+    #[test]
+    fn loopback_multicast_filters_units_and_counts_per_channel() {
+        let loopback = Ipv4Addr::LOCALHOST;
+        let (group_a, group_b) = (Ipv4Addr::new(239, 255, 41, 1), Ipv4Addr::new(239, 255, 41, 2));
+        let port = free_port();
+        
+        let config = ReceiverConfig::new(vec![
+            ChannelConfig::new(Feed::A, group_a, port).with_interface(loopback).with_units(&[1]),
+            ChannelConfig::new(Feed::B, group_b, port).with_interface(loopback),
+        ]);
+        let mut receiver = MulticastReceiver::bind(config).unwrap();
+        
+        let sender = UdpSocket::bind((loopback, 0)).unwrap();
+        sender.set_multicast_loop_v4(true).unwrap();
+        let sender = Socket::from(sender);
+        sender.set_multicast_if_v4(&loopback).unwrap();
+        let sender = UdpSocket::from(sender);
+        
+        let to_a = SocketAddrV4::new(group_a, port);
+        let to_b = SocketAddrV4::new(group_b, port);
+        for (datagram, to) in [
+            (frame(1, 10, 2), to_a),
+            (frame(2, 5, 1), to_a), // Filtered: channel A only takes unit 1
+            (frame(1, 12, 0), to_a),
+            (vec![0u8; 3], to_a),   // Malformed
+            (frame(1, 10, 2), to_b),
+            (frame(2, 5, 1), to_b),
+        ] {
+            sender.send_to(&datagram, to).unwrap();
+        }
+        
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            let Some(frame) = receiver.recv_timeout(Duration::from_secs(2)).unwrap() else {
+                break;
+            };
+            received.push((frame.feed, frame.header.unit, frame.header.sequence, frame.messages().unwrap().len()));
+        }
+        // Drain anything left so every datagram is counted
+        while receiver.try_recv().unwrap().is_some() {}
+        
+        received.sort_by_key(|&(feed, unit, sequence, _)| (feed == Feed::B, unit, sequence));
+        assert_eq!(received, vec![
+            (Feed::A, 1, 10, 2),
+            (Feed::A, 1, 12, 0),
+            (Feed::B, 1, 10, 2),
+            (Feed::B, 2, 5, 1),
+        ]);
+        
+        let a = receiver.channel_stats(0).unwrap();
+        assert_eq!((a.datagrams, a.frames, a.heartbeats, a.filtered, a.malformed), (4, 2, 1, 1, 1));
+        assert_eq!(a.last_sequence, HashMap::from([(1, 12)]));
+        
+        let b = receiver.channel_stats(1).unwrap();
+        assert_eq!((b.datagrams, b.frames, b.heartbeats, b.filtered, b.malformed), (2, 2, 0, 0, 0));
+        assert_eq!(b.last_sequence, HashMap::from([(1, 10), (2, 5)]));
+        assert!(b.bytes > 0 && b.last_received.is_some());
+        
+        receiver.reset_stats();
+        assert_eq!(receiver.channel_stats(0), Some(&ChannelStats::default()));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::{book_manager::BookManager, message::*, order_book::*};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// One way the book disagrees with the snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Discrepancy {
+    Missing(OrderBookEntry), // In the snapshot but not the book
+    Extra(OrderBookEntry),   // In the book but not the snapshot
+    QuantityMismatch { order_id: OrderId, book: u32, snapshot: u32 },
+    PriceMismatch { order_id: OrderId, book: Price, snapshot: Price },
+    SideMismatch { order_id: OrderId, book: Side, snapshot: Side },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub symbol: String,
+    pub matched: usize, // Orders identical in both
+    pub discrepancies: Vec<Discrepancy>,
+    pub repaired: bool,
+}
+
+impl ReconciliationReport {
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Resting orders for `symbol` from snapshot messages such as a Spin image's Add Orders
+pub fn snapshot_orders(messages: &[PitchMessage], symbol: &str) -> Vec<OrderBookEntry> {
+    messages
+        .iter()
+        .filter_map(|message| match message {
+            PitchMessage::AddOrder { order_id, side, quantity, symbol: order_symbol, price, pid, timestamp }
+                if order_symbol == symbol && *quantity > 0 =>
+            {
+                Some(OrderBookEntry {
+                    order_id: *order_id,
+                    price: *price,
+                    quantity: *quantity,
+                    side: *side,
+                    pid: pid.clone(),
+                    timestamp: *timestamp,
+                })
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+impl OrderBook {
+    /// Diff the book against the exchange's orders taken at the same sequence
+    pub fn reconcile(&self, snapshot: &[OrderBookEntry]) -> ReconciliationReport {
+        let expected = index_snapshot(snapshot);
+        
+        let mut report = ReconciliationReport {
+            symbol: self.symbol().to_string(),
+            matched: 0,
+            discrepancies: Vec::new(),
+            repaired: false,
+        };
+        
+        for order in self.orders() {
+            let Some(snapshot_order) = expected.get(&order.order_id) else {
+                report.discrepancies.push(Discrepancy::Extra(order.clone()));
+                continue;
+            };
+            
+            let before = report.discrepancies.len();
+            if order.side != snapshot_order.side {
+                report.discrepancies.push(Discrepancy::SideMismatch {
+                    order_id: order.order_id,
+                    book: order.side,
+                    snapshot: snapshot_order.side,
+                });
+            }
+            if order.price != snapshot_order.price {
+                report.discrepancies.push(Discrepancy::PriceMismatch {
+                    order_id: order.order_id,
+                    book: order.price,
+                    snapshot: snapshot_order.price,
+                });
+            }
+            if order.quantity != snapshot_order.quantity {
+                report.discrepancies.push(Discrepancy::QuantityMismatch {
+                    order_id: order.order_id,
+                    book: order.quantity,
+                    snapshot: snapshot_order.quantity,
+                });
+            }
+            if report.discrepancies.len() == before {
+                report.matched += 1;
+            }
+        }
+        
+        // Walk the snapshot itself so missing orders are reported in its queue order
+        let mut seen = HashSet::with_capacity(snapshot.len());
+        for order in snapshot {
+            if seen.insert(order.order_id) && !self.contains_order(order.order_id) {
+                report.discrepancies.push(Discrepancy::Missing(order.clone()));
+            }
+        }
+        
+        report
+    }
+    
+    /// Reconcile, then make the book match the snapshot.
+    ///
+    /// Quantity decreases are applied in place and keep time priority; any other
+    /// correction re-queues the order at the back of its level, as the exchange would.
+    pub fn reconcile_and_repair(&mut self, snapshot: &[OrderBookEntry]) -> ReconciliationReport {
+        let mut report = self.reconcile(snapshot);
+        if report.is_clean() {
+            return report;
+        }
+        
+        let expected = index_snapshot(snapshot);
+        
+        for discrepancy in &report.discrepancies {
+            match discrepancy {
+                Discrepancy::Extra(order) => {
+                    self.remove_order(order.order_id);
+                },
+                Discrepancy::Missing(order) => {
+                    self.restore_order(order.clone());
+                },
+                Discrepancy::QuantityMismatch { order_id, book, snapshot } if snapshot < book => {
+                    // Mismatches on the same order may already have re-queued it
+                    if self.order(*order_id).is_some_and(|order| order.quantity == *book) {
+                        let _ = self.reduce_order(*order_id, book - snapshot);
+                    }
+                },
+                Discrepancy::QuantityMismatch { order_id, .. }
+                | Discrepancy::PriceMismatch { order_id, .. }
+                | Discrepancy::SideMismatch { order_id, .. } => {
+                    let Some(&target) = expected.get(order_id) else {
+                        continue;
+                    };
+                    let up_to_date = self.order(*order_id).is_some_and(|order| {
+                        order.side == target.side && order.price == target.price && order.quantity == target.quantity
+                    });
+                    if !up_to_date {
+                        self.remove_order(*order_id);
+                        self.restore_order(target.clone());
+                    }
+                },
+            }
+        }
+        
+        report.repaired = true;
+        report
+    }
+}
+
+/// First occurrence of each order ID wins
+fn index_snapshot(snapshot: &[OrderBookEntry]) -> HashMap<OrderId, &OrderBookEntry> {
+    let mut expected = HashMap::with_capacity(snapshot.len());
+    for order in snapshot {
+        expected.entry(order.order_id).or_insert(order);
+    }
+    expected
+}
+
+impl BookManager {
+    /// Reconcile one symbol's book, optionally repairing it and the order index.
+    /// `None` if the symbol has no book.
+    pub fn reconcile(&mut self, symbol: &str, snapshot: &[OrderBookEntry], repair: bool) -> Option<ReconciliationReport> {
+        if !repair {
+            return self.book(symbol).map(|book| book.reconcile(snapshot));
+        }
+        
+        let report = self.book_mut(symbol)?.reconcile_and_repair(snapshot);
+        
+        for discrepancy in &report.discrepancies {
+            match discrepancy {
+                Discrepancy::Extra(order) => self.forget_order(order.order_id),
+                Discrepancy::Missing(order) => self.index_order(order.order_id, symbol),
+                _ => {},
+            }
+        }
+        
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PitchError;
+    use chrono::{DateTime, TimeZone, Utc};
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn add(order_id: u64, side: Side, quantity: u32, price: u64) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: at(order_id as i64),
+            order_id: OrderId(order_id),
+            side,
+            quantity,
+            symbol: "AAPL".to_string(),
+            price: Price(price),
+            pid: "TEST".to_string(),
+        }
+    }
+    
+    fn manager(messages: &[PitchMessage]) -> BookManager {
+        let mut manager = BookManager::new();
+        assert!(manager.apply_messages(messages).is_empty());
+        manager
+    }
+    
+    fn queue(manager: &BookManager, side: Side, price: u64) -> Vec<(u64, u32)> {
+        manager
+            .book("AAPL")
+            .and_then(|book| book.level(side, Price(price)))
+            .map(|level| level.orders().map(|order| (order.order_id.0, order.quantity)).collect())
+            .unwrap_or_default()
+    }
+    
+    #[test]
+    fn matching_snapshot_is_clean() {
+        let messages = [add(1, Side::Buy, 100, 1_000), add(2, Side::Sell, 200, 1_010)];
+        let mut manager = manager(&messages);
+        
+        let report = manager.reconcile("AAPL", &snapshot_orders(&messages, "AAPL"), false).unwrap();
+        
+        assert!(report.is_clean());
+        assert_eq!(report.matched, 2);
+        assert!(!report.repaired);
+        assert!(manager.reconcile("MSFT", &[], false).is_none());
+    }
+    
+    #[test]
+    fn every_kind_of_discrepancy_is_reported() {
+        let mut manager = manager(&[
+            add(1, Side::Buy, 100, 1_000),
+            add(2, Side::Buy, 100, 1_000),
+            add(3, Side::Sell, 100, 1_010),
+            add(4, Side::Sell, 100, 1_020),
+            add(5, Side::Buy, 100, 990),
+        ]);
+        let snapshot = snapshot_orders(&[
+            add(1, Side::Buy, 100, 1_000),
+            add(2, Side::Buy, 60, 1_000),
+            add(3, Side::Sell, 100, 1_015),
+            add(4, Side::Buy, 100, 1_020),
+            add(6, Side::Sell, 300, 1_030),
+        ], "AAPL");
+        
+        let report = manager.reconcile("AAPL", &snapshot, false).unwrap();
+        
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.discrepancies, vec![
+            Discrepancy::QuantityMismatch { order_id: OrderId(2), book: 100, snapshot: 60 },
+            Discrepancy::Extra(manager.book("AAPL").unwrap().order(OrderId(5)).unwrap().clone()),
+            Discrepancy::PriceMismatch { order_id: OrderId(3), book: Price(1_010), snapshot: Price(1_015) },
+            Discrepancy::SideMismatch { order_id: OrderId(4), book: Side::Sell, snapshot: Side::Buy },
+            Discrepancy::Missing(snapshot[4].clone()),
+        ]);
+        
+        // Reporting alone leaves the book untouched
+        assert_eq!(queue(&manager, Side::Buy, 1_000), vec![(1, 100), (2, 100)]);
+        assert!(manager.book("AAPL").unwrap().contains_order(OrderId(5)));
+    }
+    
+    #[test]
+    fn repair_makes_the_book_match_the_snapshot() {
+        let mut manager = manager(&[
+            add(1, Side::Buy, 100, 1_000),
+            add(2, Side::Buy, 100, 1_000),
+            add(3, Side::Buy, 100, 1_000),
+            add(4, Side::Sell, 100, 1_010),
+        ]);
+        let snapshot = snapshot_orders(&[
+            add(1, Side::Buy, 40, 1_000),  // Smaller: keeps priority
+            add(2, Side::Buy, 200, 1_000), // Larger: loses priority
+            add(3, Side::Buy, 100, 1_000),
+            add(5, Side::Sell, 300, 1_020),
+        ], "AAPL");
+        
+        let report = manager.reconcile("AAPL", &snapshot, true).unwrap();
+        
+        assert!(report.repaired);
+        assert_eq!(report.discrepancies.len(), 4);
+        assert_eq!(queue(&manager, Side::Buy, 1_000), vec![(1, 40), (3, 100), (2, 200)]);
+        assert!(manager.reconcile("AAPL", &snapshot, false).unwrap().is_clean());
+        assert!(manager.book("AAPL").unwrap().diagnose().is_empty());
+        
+        // The order index follows the repair
+        assert_eq!(manager.symbol_for_order(OrderId(5)), Some("AAPL"));
+        assert_eq!(manager.symbol_for_order(OrderId(4)), None);
+        assert!(matches!(
+            manager.apply_message(&PitchMessage::DeleteOrder { timestamp: at(60), order_id: OrderId(4) }),
+            Err(PitchError::UnknownOrder(OrderId(4)))
+        ));
+        manager.apply_message(&PitchMessage::DeleteOrder { timestamp: at(60), order_id: OrderId(5) }).unwrap();
+        assert_eq!(manager.book("AAPL").unwrap().best_ask(), None);
+    }
+}
@@ -0,0 +1,664 @@
+use crate::{capture::*, error::*, parser::PitchParser, pcap::PcapReader};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Anything that yields recorded frames in order
+pub trait FrameSource {
+    fn next_frame(&mut self) -> Result<Option<RecordedFrame>>;
+    
+    /// Jump to a start position without reading every frame; `false` if unsupported
+    fn seek(&mut self, _start: &StartPosition) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+impl<R: Read + Seek> FrameSource for CaptureReader<R> {
+    fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        CaptureReader::next_frame(self)
+    }
+    
+    fn seek(&mut self, start: &StartPosition) -> Result<bool> {
+        match start {
+            StartPosition::Beginning => self.rewind()?,
+            StartPosition::Sequence(sequence) => self.seek_to_sequence(*sequence)?,
+            StartPosition::Time(time) => self.seek_to_time(*time)?,
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> FrameSource for PcapReader<R> {
+    fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        while let Some(packet) = self.next_packet()? {
+            // Skip non-PITCH payloads that happen to match the filter, and frames whose
+            // declared length disagrees with the datagram
+            let Ok(header) = PitchParser::read_header(&packet.payload) else {
+                continue;
+            };
+            if header.length as usize != packet.payload.len() {
+                continue;
+            }
+            
+            return Ok(Some(RecordedFrame {
+                received_at: packet.timestamp,
+                header,
+                data: packet.payload,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+/// Back-to-back serialized frames with no receive timestamps
+pub struct RawFrameSource {
+    data: Vec<u8>,
+    position: usize,
+    last_time: DateTime<Utc>,
+}
+
+impl RawFrameSource {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            position: 0,
+            last_time: DateTime::UNIX_EPOCH,
+        }
+    }
+}
+
+impl FrameSource for RawFrameSource {
+    fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        if self.position >= self.data.len() {
+            return Ok(None);
+        }
+        
+        let remaining = &self.data[self.position..];
+        let header = PitchParser::read_header(remaining)?;
+        let length = header.length as usize;
+        if length < 8 || remaining.len() < length {
+            return Err(PitchError::InsufficientData {
+                expected: length.max(8),
+                actual: remaining.len(),
+            });
+        }
+        
+        let data = remaining[..length].to_vec();
+        self.position += length;
+        
+        // Exchange time stands in for the missing receive time
+        let mut frame = RecordedFrame {
+            received_at: self.last_time,
+            header,
+            data,
+        };
+        if let Some(exchange_time) = frame.exchange_time() {
+            self.last_time = exchange_time;
+            frame.received_at = exchange_time;
+        }
+        
+        Ok(Some(frame))
+    }
+}
+
+/// Destination for replayed frames
+pub trait FrameSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()>;
+}
+
+/// Publishes each frame as one UDP datagram
+pub struct UdpSink {
+    socket: UdpSocket,
+    destination: SocketAddrV4,
+}
+
+impl UdpSink {
+    pub fn multicast(destination: SocketAddrV4, interface: Ipv4Addr, ttl: u32) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_multicast_if_v4(&interface)?;
+        socket.set_multicast_ttl_v4(ttl)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.bind(&SocketAddrV4::new(interface, 0).into())?;
+        
+        Ok(Self {
+            socket: socket.into(),
+            destination,
+        })
+    }
+}
+
+impl FrameSink for UdpSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.socket.send_to(frame, self.destination)?;
+        Ok(())
+    }
+}
+
+/// Streams frames to every connected TCP client; frames are self-delimiting
+pub struct TcpSink {
+    listener: TcpListener,
+    clients: Vec<TcpStream>,
+}
+
+impl TcpSink {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+    
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+    
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+    
+    /// Block until at least `count` clients are connected or `timeout` elapses
+    pub fn wait_for_clients(&mut self, count: usize, timeout: Duration) -> Result<usize> {
+        let deadline = Instant::now() + timeout;
+        
+        while self.clients.len() < count && Instant::now() < deadline {
+            self.accept_pending()?;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        
+        Ok(self.clients.len())
+    }
+    
+    fn accept_pending(&mut self) -> Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    stream.set_nodelay(true)?;
+                    self.clients.push(stream);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl FrameSink for TcpSink {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.accept_pending()?;
+        
+        // Disconnected clients are dropped instead of stopping the replay
+        self.clients.retain_mut(|client| client.write_all(frame).is_ok());
+        Ok(())
+    }
+}
+
+/// Where to take inter-frame timing from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimingSource {
+    Exchange, // Message timestamps inside the frames
+    Receive,  // Receive timestamps recorded with the frames
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Pacing {
+    Original,
+    Speed(f64), // Multiplier over the original pacing
+    AsFastAsPossible,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StartPosition {
+    Beginning,
+    Sequence(u32),
+    Time(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayConfig {
+    pub pacing: Pacing,
+    pub timing: TimingSource,
+    pub start: StartPosition,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            pacing: Pacing::Original,
+            timing: TimingSource::Exchange,
+            start: StartPosition::Beginning,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayStats {
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+    pub frames_skipped: u64, // Before the start position
+    pub max_lag: Duration,   // Worst delay behind the pacing schedule
+}
+
+/// Cloneable handle to pause, resume or stop a running replay from another thread
+#[derive(Debug, Clone, Default)]
+pub struct ReplayControl {
+    paused: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ReplayControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+    
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+    
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+    
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+    
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+/// Replays recorded frames to a sink reproducing their original timing
+pub struct ReplayEngine {
+    config: ReplayConfig,
+    control: ReplayControl,
+}
+
+impl ReplayEngine {
+    pub fn new(config: ReplayConfig) -> Result<Self> {
+        if let Pacing::Speed(speed) = config.pacing {
+            if !speed.is_finite() || speed <= 0.0 {
+                return Err(PitchError::Config(format!("replay speed must be a positive multiplier, got {}", speed)));
+            }
+        }
+        
+        Ok(Self {
+            config,
+            control: ReplayControl::default(),
+        })
+    }
+    
+    pub fn control(&self) -> ReplayControl {
+        self.control.clone()
+    }
+    
+    pub fn run<S: FrameSource, K: FrameSink>(&mut self, source: &mut S, sink: &mut K) -> Result<ReplayStats> {
+        let mut stats = ReplayStats::default();
+        let mut started = source.seek(&self.config.start)?;
+        
+        // Schedule anchor: (frame time, wall clock) of the first frame after (re)starting
+        let mut anchor: Option<(DateTime<Utc>, Instant)> = None;
+        let mut last_time: Option<DateTime<Utc>> = None;
+        
+        while let Some(frame) = source.next_frame()? {
+            if !started {
+                if !self.is_at_start(&frame) {
+                    stats.frames_skipped += 1;
+                    continue;
+                }
+                started = true;
+            }
+            
+            if self.wait_while_paused() {
+                // Re-anchor so the pause does not turn into a burst
+                anchor = None;
+            }
+            
+            if self.control.is_stopped() {
+                break;
+            }
+            
+            let frame_time = self.frame_time(&frame).or(last_time);
+            last_time = frame_time;
+            
+            if let Some(time) = frame_time {
+                if let Some(lag) = self.pace(&mut anchor, time) {
+                    stats.max_lag = stats.max_lag.max(lag);
+                }
+            }
+            
+            if self.control.is_stopped() {
+                break;
+            }
+            
+            sink.send_frame(&frame.data)?;
+            stats.frames_sent += 1;
+            stats.bytes_sent += frame.data.len() as u64;
+        }
+        
+        Ok(stats)
+    }
+    
+    fn is_at_start(&self, frame: &RecordedFrame) -> bool {
+        match self.config.start {
+            StartPosition::Beginning => true,
+            StartPosition::Sequence(sequence) => {
+                // Serial-number comparison so a frame straddling the u32 wrap still counts
+                let end = frame.header.sequence.wrapping_add((frame.header.count as u32).max(1));
+                (end.wrapping_sub(sequence) as i32) > 0
+            },
+            StartPosition::Time(time) => frame.exchange_time().is_some_and(|exchange_time| exchange_time >= time),
+        }
+    }
+    
+    fn frame_time(&self, frame: &RecordedFrame) -> Option<DateTime<Utc>> {
+        match self.config.timing {
+            TimingSource::Exchange => frame.exchange_time(),
+            TimingSource::Receive => Some(frame.received_at),
+        }
+    }
+    
+    /// Returns true if the replay was paused
+    fn wait_while_paused(&self) -> bool {
+        let mut paused = false;
+        
+        while self.control.is_paused() && !self.control.is_stopped() {
+            paused = true;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        
+        paused
+    }
+    
+    /// Sleep until `time` is due; returns how late the frame is, if pacing applies.
+    /// A pause while waiting re-anchors the schedule at the resumed frame.
+    fn pace(&self, anchor: &mut Option<(DateTime<Utc>, Instant)>, time: DateTime<Utc>) -> Option<Duration> {
+        let speed = match self.config.pacing {
+            Pacing::Original => 1.0,
+            Pacing::Speed(speed) => speed,
+            Pacing::AsFastAsPossible => return None,
+        };
+        
+        let (start_time, start_instant) = *anchor.get_or_insert((time, Instant::now()));
+        let elapsed = (time - start_time).to_std().unwrap_or_default();
+        let due = start_instant + elapsed.div_f64(speed);
+        
+        loop {
+            if self.control.is_stopped() {
+                return None;
+            }
+            
+            if self.wait_while_paused() {
+                *anchor = Some((time, Instant::now()));
+                return Some(Duration::ZERO);
+            }
+            
+            let now = Instant::now();
+            if now >= due {
+                return Some(now - due);
+            }
+            
+            let remaining = due - now;
+            if remaining > Duration::from_millis(2) {
+                // Short sleeps keep pause and stop responsive during long quiet periods
+                std::thread::sleep((remaining - Duration::from_millis(1)).min(Duration::from_millis(50)));
+            } else {
+                std::hint::spin_loop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::*;
+    use crate::pcap::{PcapWriter, PcapWriterConfig};
+    use crate::simulator::PitchSimulator;
+    use chrono::TimeZone;
+    use std::collections::VecDeque;
+    use std::io::Cursor;
+    use std::sync::Mutex;
+    
+    const BASE_NANOS: i64 = 1_700_000_000_000_000_000;
+    // Sends are stamped after the engine anchors its schedule, so spacing can come up a hair short
+    const SLACK: Duration = Duration::from_millis(5);
+    
+    fn at_millis(millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(BASE_NANOS + millis * 1_000_000)
+    }
+    
+    /// Two-message frame at `sequence` with its exchange time `millis` after the base
+    fn frame(sequence: u32, millis: i64) -> RecordedFrame {
+        let timestamp = at_millis(millis);
+        let messages: Vec<PitchMessage> = (0..2)
+            .map(|i| PitchMessage::DeleteOrder {
+                timestamp,
+                order_id: OrderId(sequence as u64 * 2 + i),
+            })
+            .collect();
+        let header = SequencedUnitHeader { length: 0, count: 2, unit: 1, sequence };
+        let data = PitchSimulator::new().serialize_frame(&header, &messages).unwrap();
+        
+        RecordedFrame {
+            received_at: timestamp,
+            header: PitchParser::read_header(&data).unwrap(),
+            data,
+        }
+    }
+    
+    struct VecSource(VecDeque<RecordedFrame>);
+    
+    impl FrameSource for VecSource {
+        fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+            Ok(self.0.pop_front())
+        }
+    }
+    
+    /// Records the sequence of each frame sent and when it was sent
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<Mutex<Vec<(u32, Instant)>>>);
+    
+    impl RecordingSink {
+        fn sequences(&self) -> Vec<u32> {
+            self.0.lock().unwrap().iter().map(|&(sequence, _)| sequence).collect()
+        }
+        
+        fn span(&self) -> Duration {
+            let sent = self.0.lock().unwrap();
+            sent.last().unwrap().1 - sent.first().unwrap().1
+        }
+    }
+    
+    impl FrameSink for RecordingSink {
+        fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+            let header = PitchParser::read_header(frame)?;
+            self.0.lock().unwrap().push((header.sequence, Instant::now()));
+            Ok(())
+        }
+    }
+    
+    fn source(frames: &[(u32, i64)]) -> VecSource {
+        VecSource(frames.iter().map(|&(sequence, millis)| frame(sequence, millis)).collect())
+    }
+    
+    fn replay(config: ReplayConfig, frames: &[(u32, i64)]) -> (ReplayStats, RecordingSink) {
+        let mut sink = RecordingSink::default();
+        let stats = ReplayEngine::new(config).unwrap().run(&mut source(frames), &mut sink).unwrap();
+        (stats, sink)
+    }
+    
+    #[test]
+    fn original_pacing_follows_exchange_time() {
+        let (stats, sink) = replay(ReplayConfig::default(), &[(1, 0), (3, 40), (5, 80)]);
+        
+        assert_eq!(stats.frames_sent, 3);
+        assert_eq!(sink.sequences(), vec![1, 3, 5]);
+        assert!(sink.span() + SLACK >= Duration::from_millis(80), "{:?}", sink.span());
+    }
+    
+    #[test]
+    fn speed_multiplier_compresses_gaps() {
+        let config = ReplayConfig {
+            pacing: Pacing::Speed(4.0),
+            ..ReplayConfig::default()
+        };
+        let (_, sink) = replay(config, &[(1, 0), (3, 200), (5, 400)]);
+        
+        assert!(sink.span() + SLACK >= Duration::from_millis(100), "{:?}", sink.span());
+        assert!(sink.span() < Duration::from_millis(400), "{:?}", sink.span());
+    }
+    
+    #[test]
+    fn as_fast_as_possible_ignores_timing() {
+        let config = ReplayConfig {
+            pacing: Pacing::AsFastAsPossible,
+            ..ReplayConfig::default()
+        };
+        let (stats, sink) = replay(config, &[(1, 0), (3, 60_000), (5, 120_000)]);
+        
+        assert_eq!(stats.frames_sent, 3);
+        assert!(sink.span() < Duration::from_secs(1));
+    }
+    
+    #[test]
+    fn invalid_speeds_are_rejected() {
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = ReplayConfig {
+                pacing: Pacing::Speed(speed),
+                ..ReplayConfig::default()
+            };
+            assert!(matches!(ReplayEngine::new(config), Err(PitchError::Config(_))), "speed {}", speed);
+        }
+    }
+    
+    #[test]
+    fn pause_holds_frames_until_resumed() {
+        let mut engine = ReplayEngine::new(ReplayConfig {
+            pacing: Pacing::AsFastAsPossible,
+            ..ReplayConfig::default()
+        })
+        .unwrap();
+        let control = engine.control();
+        control.pause();
+        
+        let sink = RecordingSink::default();
+        let mut thread_sink = sink.clone();
+        let handle = std::thread::spawn(move || engine.run(&mut source(&[(1, 0), (3, 10)]), &mut thread_sink));
+        
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(sink.sequences().is_empty());
+        
+        let resumed = Instant::now();
+        control.resume();
+        let stats = handle.join().unwrap().unwrap();
+        
+        assert_eq!(stats.frames_sent, 2);
+        assert!(sink.0.lock().unwrap().iter().all(|&(_, sent)| sent >= resumed));
+    }
+    
+    #[test]
+    fn resume_reanchors_instead_of_bursting() {
+        let mut engine = ReplayEngine::new(ReplayConfig::default()).unwrap();
+        let control = engine.control();
+        
+        let sink = RecordingSink::default();
+        let mut thread_sink = sink.clone();
+        let handle = std::thread::spawn(move || engine.run(&mut source(&[(1, 0), (3, 100), (5, 200)]), &mut thread_sink));
+        
+        // Pause while the second frame is waiting, for longer than the rest of the schedule
+        std::thread::sleep(Duration::from_millis(30));
+        control.pause();
+        std::thread::sleep(Duration::from_millis(300));
+        control.resume();
+        handle.join().unwrap().unwrap();
+        
+        let sent = sink.0.lock().unwrap().clone();
+        assert_eq!(sent.len(), 3);
+        // The third frame keeps its 100ms spacing from the resumed second frame
+        assert!(sent[2].1 - sent[1].1 + SLACK >= Duration::from_millis(100), "{:?}", sent[2].1 - sent[1].1);
+    }
+    
+    #[test]
+    fn stop_ends_the_replay() {
+        let mut engine = ReplayEngine::new(ReplayConfig::default()).unwrap();
+        let control = engine.control();
+        control.stop();
+        
+        let mut sink = RecordingSink::default();
+        let stats = engine.run(&mut source(&[(1, 0), (3, 10)]), &mut sink).unwrap();
+        
+        assert_eq!(stats.frames_sent, 0);
+    }
+    
+    #[test]
+    fn start_from_sequence_includes_the_frame_containing_it() {
+        let config = ReplayConfig {
+            pacing: Pacing::AsFastAsPossible,
+            start: StartPosition::Sequence(4),
+            ..ReplayConfig::default()
+        };
+        let (stats, sink) = replay(config, &[(1, 0), (3, 1), (5, 2)]);
+        
+        assert_eq!(sink.sequences(), vec![3, 5]);
+        assert_eq!(stats.frames_skipped, 1);
+    }
+    
+    #[test]
+    fn start_from_sequence_across_the_wrap() {
+        let config = ReplayConfig {
+            pacing: Pacing::AsFastAsPossible,
+            start: StartPosition::Sequence(u32::MAX),
+            ..ReplayConfig::default()
+        };
+        // The second frame covers u32::MAX - 1 and u32::MAX, so its end wraps to 0
+        let (stats, sink) = replay(config, &[(u32::MAX - 3, 0), (u32::MAX - 1, 1), (0, 2)]);
+        
+        assert_eq!(sink.sequences(), vec![u32::MAX - 1, 0]);
+        assert_eq!(stats.frames_skipped, 1);
+    }
+    
+    #[test]
+    fn start_from_time_skips_earlier_frames() {
+        let config = ReplayConfig {
+            pacing: Pacing::AsFastAsPossible,
+            start: StartPosition::Time(at_millis(15)),
+            ..ReplayConfig::default()
+        };
+        let (stats, sink) = replay(config, &[(1, 0), (3, 10), (5, 20), (7, 30)]);
+        
+        assert_eq!(sink.sequences(), vec![5, 7]);
+        assert_eq!(stats.frames_skipped, 2);
+    }
+    
+    #[test]
+    fn pcap_source_skips_payloads_that_are_not_whole_frames() {
+        let mut writer = PcapWriter::new(Vec::new(), PcapWriterConfig::default()).unwrap();
+        let good = frame(1, 0).data;
+        let mut padded = frame(3, 1).data;
+        padded.extend_from_slice(&[0; 4]);
+        
+        writer.write_frame(at_millis(0), &good).unwrap();
+        writer.write_frame(at_millis(1), &padded).unwrap();
+        // Too short for a Sequenced Unit Header
+        let config = PcapWriterConfig::default();
+        writer.write_packet(at_millis(2), config.source, config.default_destination, &[0xFF; 3]).unwrap();
+        writer.write_frame(at_millis(3), &frame(5, 3).data).unwrap();
+        
+        let mut reader = PcapReader::new(Cursor::new(writer.into_inner().unwrap())).unwrap();
+        let mut sequences = Vec::new();
+        while let Some(frame) = FrameSource::next_frame(&mut reader).unwrap() {
+            sequences.push(frame.header.sequence);
+        }
+        
+        assert_eq!(sequences, vec![1, 5]);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::message::*;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use byteorder::{LittleEndian, WriteBytesExt};
 use std::io::Write;
 
@@ -169,6 +169,79 @@ impl PitchSimulator {
                 buffer.write_u8(0)?;
             },
             
+            PitchMessage::ReduceSize { timestamp, order_id, cancelled_quantity } => {
+                buffer.write_u8(22)?;
+                buffer.write_u8(0x39)?;
+                buffer.write_u64::<LittleEndian>(timestamp.timestamp_nanos_opt().unwrap_or(0) as u64)?;
+                buffer.write_u64::<LittleEndian>(order_id.0)?;
+                buffer.write_u32::<LittleEndian>(*cancelled_quantity)?;
+            },
+            
+            PitchMessage::ModifyOrder { timestamp, order_id, quantity, price } => {
+                buffer.write_u8(31)?;
+                buffer.write_u8(0x3A)?;
+                buffer.write_u64::<LittleEndian>(timestamp.timestamp_nanos_opt().unwrap_or(0) as u64)?;
+                buffer.write_u64::<LittleEndian>(order_id.0)?;
+                buffer.write_u32::<LittleEndian>(*quantity)?;
+                buffer.write_u64::<LittleEndian>(price.0)?;
+                buffer.write_u8(0)?;
+            },
+            
+            PitchMessage::DeleteOrder { timestamp, order_id } => {
+                buffer.write_u8(18)?;
+                buffer.write_u8(0x3C)?;
+                buffer.write_u64::<LittleEndian>(timestamp.timestamp_nanos_opt().unwrap_or(0) as u64)?;
+                buffer.write_u64::<LittleEndian>(order_id.0)?;
+            },
+            
+            PitchMessage::Trade {
+                timestamp, symbol, quantity, price, execution_id, order_id, contra_order_id, pid, contra_pid,
+                trade_type, trade_designation, trade_report_type, trade_transaction_time, flags,
+            } => {
+                buffer.write_u8(72)?;
+                buffer.write_u8(0x3D)?;
+                buffer.write_u64::<LittleEndian>(timestamp.timestamp_nanos_opt().unwrap_or(0) as u64)?;
+                
+                let mut symbol_bytes = [b' '; 6];
+                let symbol_len = symbol.len().min(6);
+                symbol_bytes[..symbol_len].copy_from_slice(&symbol.as_bytes()[..symbol_len]);
+                buffer.write_all(&symbol_bytes)?;
+                
+                buffer.write_u32::<LittleEndian>(*quantity)?;
+                buffer.write_u64::<LittleEndian>(price.0)?;
+                buffer.write_u64::<LittleEndian>(execution_id.0)?;
+                buffer.write_u64::<LittleEndian>(order_id.0)?;
+                buffer.write_u64::<LittleEndian>(contra_order_id.0)?;
+                
+                let mut pid_bytes = [b' '; 4];
+                let pid_len = pid.len().min(4);
+                pid_bytes[..pid_len].copy_from_slice(&pid.as_bytes()[..pid_len]);
+                buffer.write_all(&pid_bytes)?;
+                
+                let mut contra_pid_bytes = [b' '; 4];
+                let contra_pid_len = contra_pid.len().min(4);
+                contra_pid_bytes[..contra_pid_len].copy_from_slice(&contra_pid.as_bytes()[..contra_pid_len]);
+                buffer.write_all(&contra_pid_bytes)?;
+                
+                buffer.write_u8(*trade_type as u8)?;
+                buffer.write_u8(*trade_designation as u8)?;
+                buffer.write_u8(*trade_report_type as u8)?;
+                buffer.write_u64::<LittleEndian>(trade_transaction_time.timestamp_nanos_opt().unwrap_or(0) as u64)?;
+                buffer.write_u8(*flags)?;
+            },
+            
+            PitchMessage::UnitClear { .. } => {
+                buffer.write_u8(6)?;
+                buffer.write_u8(0x97)?;
+                buffer.write_all(&[0u8; 4])?;
+            },
+            
+            PitchMessage::EndOfSession { .. } => {
+                buffer.write_u8(6)?;
+                buffer.write_u8(0x2D)?;
+                buffer.write_all(&[0u8; 4])?;
+            },
+            
             _ => {
                 return Err(crate::error::PitchError::Parse("Unsupported message type".to_string()));
             }
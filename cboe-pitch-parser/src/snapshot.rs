@@ -0,0 +1,344 @@
+use crate::{book_manager::BookManager, message::*, order_book::*};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub price: Price,
+    pub quantity: u64,
+    pub order_count: usize,
+}
+
+/// Immutable top-of-book depth for readers on other threads
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub symbol: String,
+    pub version: u64,          // Increments with every publish
+    pub sequence: Option<u32>, // Last message applied to the book, if known
+    pub timestamp: Option<DateTime<Utc>>,
+    pub trading_status: TradingStatus,
+    pub state: BookState,
+    pub bids: Vec<DepthLevel>, // Best first
+    pub asks: Vec<DepthLevel>, // Best first
+}
+
+impl DepthSnapshot {
+    pub fn from_book(book: &OrderBook, depth: usize, sequence: Option<u32>) -> Self {
+        let levels = |side| {
+            book.levels(side)
+                .take(depth)
+                .map(|level| DepthLevel {
+                    price: level.price(),
+                    quantity: level.quantity(),
+                    order_count: level.order_count(),
+                })
+                .collect()
+        };
+        
+        Self {
+            symbol: book.symbol().to_string(),
+            version: 0,
+            sequence,
+            timestamp: book.last_update(),
+            trading_status: book.trading_status(),
+            state: book.state(),
+            bids: levels(Side::Buy),
+            asks: levels(Side::Sell),
+        }
+    }
+    
+    /// Older than `sequence`, e.g. the feed handler's latest watermark for the unit
+    pub fn is_stale(&self, sequence: u32) -> bool {
+        self.sequence.is_none_or(|own| own < sequence)
+    }
+}
+
+/// One reader's mailbox holding the newest snapshot it has not taken yet.
+///
+/// Ownership of the `Arc` moves with each swap, so the writer and the reader
+/// each finish in a single atomic operation without locks or retries.
+struct Slot {
+    pending: AtomicPtr<DepthSnapshot>, // From `Arc::into_raw`, or null
+}
+
+impl Slot {
+    fn new() -> Self {
+        Self {
+            pending: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+    
+    fn put(&self, snapshot: Arc<DepthSnapshot>) {
+        let new = Arc::into_raw(snapshot).cast_mut();
+        let old = self.pending.swap(new, Ordering::AcqRel);
+        if !old.is_null() {
+            // SAFETY: non-null pointers in the slot always come from `Arc::into_raw`,
+            // and the swap handed this one to us alone
+            drop(unsafe { Arc::from_raw(old) });
+        }
+    }
+    
+    fn take(&self) -> Option<Arc<DepthSnapshot>> {
+        let taken = self.pending.swap(ptr::null_mut(), Ordering::AcqRel);
+        // SAFETY: as in `put`
+        (!taken.is_null()).then(|| unsafe { Arc::from_raw(taken) })
+    }
+}
+
+impl std::fmt::Debug for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Slot")
+            .field("pending", &!self.pending.load(Ordering::Relaxed).is_null())
+            .finish()
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.take();
+    }
+}
+
+/// Publishes one symbol's depth from the feed-handler thread to any number of readers
+#[derive(Debug)]
+pub struct SnapshotPublisher {
+    symbol: String,
+    depth: usize,
+    interval: Option<Duration>, // Minimum spacing for `maybe_publish`
+    last_published: Option<Instant>,
+    latest: Option<Arc<DepthSnapshot>>,
+    version: u64,
+    slots: Vec<Arc<Slot>>,
+}
+
+impl SnapshotPublisher {
+    pub fn new(symbol: &str, depth: usize) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            depth,
+            interval: None,
+            last_published: None,
+            latest: None,
+            version: 0,
+            slots: Vec::new(),
+        }
+    }
+    
+    /// Throttle `maybe_publish` to at most one snapshot per `interval`
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+    
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+    
+    /// A reader to move to another thread; it starts at the latest snapshot, if any
+    pub fn subscribe(&mut self) -> SnapshotReader {
+        let slot = Arc::new(Slot::new());
+        if let Some(latest) = &self.latest {
+            slot.put(Arc::clone(latest));
+        }
+        self.slots.push(Arc::clone(&slot));
+        
+        SnapshotReader {
+            slot,
+            current: None,
+        }
+    }
+    
+    /// Build a snapshot of `book` and hand it to every reader
+    pub fn publish(&mut self, book: &OrderBook, sequence: Option<u32>) -> Arc<DepthSnapshot> {
+        self.version += 1;
+        let mut snapshot = DepthSnapshot::from_book(book, self.depth, sequence);
+        snapshot.version = self.version;
+        let snapshot = Arc::new(snapshot);
+        
+        // Readers that were dropped leave their slot with us as the only owner
+        self.slots.retain(|slot| Arc::strong_count(slot) > 1);
+        for slot in &self.slots {
+            slot.put(Arc::clone(&snapshot));
+        }
+        
+        self.latest = Some(Arc::clone(&snapshot));
+        snapshot
+    }
+    
+    /// Publish the symbol's book from `manager`, stamped with its unit's watermark
+    pub fn publish_from(&mut self, manager: &BookManager) -> Option<Arc<DepthSnapshot>> {
+        let book = manager.book(&self.symbol)?;
+        let sequence = manager.unit_of(&self.symbol).and_then(|unit| manager.last_sequence(unit));
+        Some(self.publish(book, sequence))
+    }
+    
+    /// Call between frames; publishes if the interval has passed, or always without one
+    pub fn maybe_publish(&mut self, manager: &BookManager, now: Instant) -> bool {
+        let due = match (self.interval, self.last_published) {
+            (Some(interval), Some(last)) => now.duration_since(last) >= interval,
+            _ => true,
+        };
+        if !due || self.publish_from(manager).is_none() {
+            return false;
+        }
+        
+        self.last_published = Some(now);
+        true
+    }
+    
+    pub fn latest(&self) -> Option<&Arc<DepthSnapshot>> {
+        self.latest.as_ref()
+    }
+    
+    pub fn reader_count(&self) -> usize {
+        self.slots.iter().filter(|slot| Arc::strong_count(slot) > 1).count()
+    }
+}
+
+/// Wait-free view of a publisher's snapshots from one reader thread
+#[derive(Debug)]
+pub struct SnapshotReader {
+    slot: Arc<Slot>,
+    current: Option<Arc<DepthSnapshot>>,
+}
+
+impl SnapshotReader {
+    /// The newest snapshot published, or `None` before the first
+    pub fn latest(&mut self) -> Option<Arc<DepthSnapshot>> {
+        if let Some(snapshot) = self.slot.take() {
+            self.current = Some(snapshot);
+        }
+        self.current.clone()
+    }
+    
+    /// Whether a newer snapshot is waiting than the one last returned
+    pub fn has_update(&self) -> bool {
+        !self.slot.pending.load(Ordering::Acquire).is_null()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    
+    fn add(order_id: u64, quantity: u32) -> PitchMessage {
+        PitchMessage::AddOrder {
+            timestamp: Utc.timestamp_nanos(1_700_000_000_000_000_000 + order_id as i64),
+            order_id: OrderId(order_id),
+            side: Side::Buy,
+            quantity,
+            symbol: "AAPL".to_string(),
+            price: Price(1_000),
+            pid: "TEST".to_string(),
+        }
+    }
+    
+    #[test]
+    fn readers_see_monotonic_consistent_snapshots_across_threads() {
+        const PUBLISHES: u64 = 2_000;
+        
+        let mut publisher = SnapshotPublisher::new("AAPL", 5);
+        let readers: Vec<SnapshotReader> = (0..3).map(|_| publisher.subscribe()).collect();
+        
+        let handles: Vec<_> = readers
+            .into_iter()
+            .map(|mut reader| {
+                std::thread::spawn(move || {
+                    let mut last_version = 0;
+                    let mut seen = 0u64;
+                    while last_version < PUBLISHES {
+                        let Some(snapshot) = reader.latest() else {
+                            std::thread::yield_now();
+                            continue;
+                        };
+                        assert!(snapshot.version >= last_version);
+                        // Each publish adds one share, so a torn snapshot would disagree
+                        assert_eq!(snapshot.bids[0].quantity, snapshot.version);
+                        if snapshot.version > last_version {
+                            seen += 1;
+                        }
+                        last_version = snapshot.version;
+                    }
+                    seen
+                })
+            })
+            .collect();
+        
+        let publisher_thread = std::thread::spawn(move || {
+            let mut book = OrderBook::new("AAPL".to_string());
+            let mut first = None;
+            for i in 1..=PUBLISHES {
+                book.apply_message(&add(i, 1)).unwrap();
+                let snapshot = publisher.publish(&book, Some(i as u32));
+                first.get_or_insert_with(|| Arc::downgrade(&snapshot));
+            }
+            (publisher, first.unwrap())
+        });
+        
+        let (publisher, first) = publisher_thread.join().unwrap();
+        for handle in handles {
+            assert!(handle.join().unwrap() >= 1);
+        }
+        
+        // Every reader is gone, so only the publisher still holds the final snapshot
+        let last = Arc::clone(publisher.latest().unwrap());
+        assert_eq!(publisher.reader_count(), 0);
+        drop(publisher);
+        assert_eq!(Arc::strong_count(&last), 1);
+        assert!(first.upgrade().is_none());
+    }
+    
+    #[test]
+    fn dropped_readers_are_pruned_on_publish() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        book.apply_message(&add(1, 100)).unwrap();
+        
+        let mut publisher = SnapshotPublisher::new("AAPL", 5);
+        let mut kept = publisher.subscribe();
+        let dropped = publisher.subscribe();
+        let old = Arc::downgrade(&publisher.publish(&book, None));
+        
+        drop(dropped);
+        assert_eq!(publisher.reader_count(), 1);
+        assert_eq!(publisher.slots.len(), 2);
+        
+        let new = publisher.publish(&book, None);
+        assert_eq!(publisher.slots.len(), 1);
+        // The dropped reader's undelivered snapshot was freed with its slot
+        assert!(old.upgrade().is_none());
+        assert_eq!(kept.latest().unwrap().version, new.version);
+        
+        // Late subscribers start from the latest snapshot
+        assert_eq!(publisher.subscribe().latest().unwrap().version, 2);
+    }
+    
+    #[test]
+    fn maybe_publish_is_throttled_by_interval() {
+        let mut manager = BookManager::new();
+        manager.apply_message(&add(1, 100)).unwrap();
+        let start = Instant::now();
+        
+        let mut publisher = SnapshotPublisher::new("AAPL", 5).with_interval(Duration::from_millis(10));
+        let mut reader = publisher.subscribe();
+        
+        assert!(publisher.maybe_publish(&manager, start));
+        assert!(!publisher.maybe_publish(&manager, start + Duration::from_millis(5)));
+        assert!(reader.latest().is_some());
+        assert!(!reader.has_update());
+        
+        assert!(publisher.maybe_publish(&manager, start + Duration::from_millis(10)));
+        assert!(reader.has_update());
+        assert_eq!(reader.latest().unwrap().version, 2);
+        
+        // Unthrottled publishers always publish; a symbol without a book never does
+        let mut unthrottled = SnapshotPublisher::new("AAPL", 5);
+        assert!(unthrottled.maybe_publish(&manager, start));
+        assert!(unthrottled.maybe_publish(&manager, start));
+        assert!(!SnapshotPublisher::new("MSFT", 5).maybe_publish(&manager, start));
+    }
+}
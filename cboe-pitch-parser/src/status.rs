@@ -0,0 +1,283 @@
+use crate::{error::*, message::*};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What to do with a transition the session schedule does not allow
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransitionPolicy {
+    #[default]
+    Flag,   // Record it, marked illegal
+    Reject, // Return an error and keep the previous status
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusTransition {
+    pub symbol: String,
+    pub from: Option<TradingStatus>, // `None` for the first status seen
+    pub to: TradingStatus,
+    pub timestamp: DateTime<Utc>,
+    pub market_id_code: String,
+    pub legal: bool,
+}
+
+/// A stretch spent Halted or TradingSuspended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HaltPeriod {
+    pub status: TradingStatus,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>, // `None` while still halted
+}
+
+impl HaltPeriod {
+    /// Length of the halt, counting an open one up to `now`
+    pub fn duration(&self, now: DateTime<Utc>) -> TimeDelta {
+        (self.end.unwrap_or(now) - self.start).max(TimeDelta::zero())
+    }
+}
+
+/// Whether the session schedule allows moving from `from` to `to`.
+///
+/// The day runs Closed → PreMarket → PreOpen → Trading → MocTrading/PreClose →
+/// PostMarket → Closed; halts and suspensions can interrupt any phase and resume
+/// either directly or through a re-opening auction.
+pub fn is_legal_transition(from: TradingStatus, to: TradingStatus) -> bool {
+    use TradingStatus::*;
+    
+    if from == to || matches!(to, Halted | TradingSuspended | Closed) {
+        return true;
+    }
+    
+    match from {
+        Closed => matches!(to, PreMarket | PreOpen),
+        PreMarket => matches!(to, PreOpen),
+        PreOpen => matches!(to, Trading),
+        Trading => matches!(to, MocTrading | PreClose | PostMarket),
+        MocTrading => matches!(to, PreClose | PostMarket),
+        PreClose => matches!(to, PostMarket),
+        PostMarket => false,
+        Halted | TradingSuspended => matches!(to, Trading | MocTrading | PreOpen | PreClose | PostMarket | Halted | TradingSuspended),
+    }
+}
+
+/// Every Trading Status transition per symbol, validated against the session schedule
+#[derive(Debug, Clone, Default)]
+pub struct StatusTracker {
+    policy: TransitionPolicy,
+    symbols: HashMap<String, Vec<StatusTransition>>, // Oldest first
+}
+
+impl StatusTracker {
+    pub fn new(policy: TransitionPolicy) -> Self {
+        Self {
+            policy,
+            symbols: HashMap::new(),
+        }
+    }
+    
+    /// Record a Trading Status message; repeats of the current status are not transitions.
+    /// Other messages are ignored.
+    pub fn observe(&mut self, message: &PitchMessage) -> Result<Option<&StatusTransition>> {
+        let PitchMessage::TradingStatus { timestamp, symbol, trading_status, market_id_code } = message else {
+            return Ok(None);
+        };
+        
+        let from = self.current_status(symbol);
+        if from == Some(*trading_status) {
+            return Ok(None);
+        }
+        
+        let legal = from.is_none_or(|from| is_legal_transition(from, *trading_status));
+        if let (Some(from), false, TransitionPolicy::Reject) = (from, legal, self.policy) {
+            return Err(PitchError::IllegalTransition {
+                symbol: symbol.clone(),
+                from,
+                to: *trading_status,
+            });
+        }
+        
+        let transitions = self.symbols.entry(symbol.clone()).or_default();
+        transitions.push(StatusTransition {
+            symbol: symbol.clone(),
+            from,
+            to: *trading_status,
+            timestamp: *timestamp,
+            market_id_code: market_id_code.clone(),
+            legal,
+        });
+        Ok(transitions.last())
+    }
+    
+    pub fn current_status(&self, symbol: &str) -> Option<TradingStatus> {
+        self.symbols.get(symbol)?.last().map(|transition| transition.to)
+    }
+    
+    pub fn transitions(&self, symbol: &str) -> &[StatusTransition] {
+        self.symbols.get(symbol).map_or(&[], Vec::as_slice)
+    }
+    
+    /// Flagged transitions across all symbols, in no particular symbol order
+    pub fn illegal_transitions(&self) -> impl Iterator<Item = &StatusTransition> {
+        self.symbols.values().flatten().filter(|transition| !transition.legal)
+    }
+    
+    /// Status in force at `time`, or `None` before the first one seen
+    pub fn status_at(&self, symbol: &str, time: DateTime<Utc>) -> Option<TradingStatus> {
+        let transitions = self.symbols.get(symbol)?;
+        let index = transitions.partition_point(|transition| transition.timestamp <= time);
+        index.checked_sub(1).map(|index| transitions[index].to)
+    }
+    
+    /// Whether `symbol` was in continuous trading at `time`; `None` if its status was unknown
+    pub fn is_tradable_at(&self, symbol: &str, time: DateTime<Utc>) -> Option<bool> {
+        self.status_at(symbol, time).map(|status| status.is_tradable())
+    }
+    
+    /// Halts and suspensions, oldest first; moving between the two starts a new period
+    pub fn halts(&self, symbol: &str) -> Vec<HaltPeriod> {
+        let mut periods: Vec<HaltPeriod> = Vec::new();
+        
+        for transition in self.transitions(symbol) {
+            if let Some(open) = periods.last_mut().filter(|period| period.end.is_none()) {
+                open.end = Some(transition.timestamp);
+            }
+            
+            if matches!(transition.to, TradingStatus::Halted | TradingStatus::TradingSuspended) {
+                periods.push(HaltPeriod {
+                    status: transition.to,
+                    start: transition.timestamp,
+                    end: None,
+                });
+            }
+        }
+        
+        periods
+    }
+    
+    /// Total time spent in `status` (Halted or TradingSuspended), counting an open period up to `now`
+    pub fn halted_duration(&self, symbol: &str, status: TradingStatus, now: DateTime<Utc>) -> TimeDelta {
+        self.halts(symbol)
+            .iter()
+            .filter(|period| period.status == status)
+            .map(|period| period.duration(now))
+            .sum()
+    }
+    
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use TradingStatus::*;
+    
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_nanos(1_700_000_000_000_000_000 + seconds * 1_000_000_000)
+    }
+    
+    fn status(symbol: &str, trading_status: TradingStatus, seconds: i64) -> PitchMessage {
+        PitchMessage::TradingStatus {
+            timestamp: at(seconds),
+            symbol: symbol.to_string(),
+            trading_status,
+            market_id_code: String::new(),
+        }
+    }
+    
+    fn observe_all(tracker: &mut StatusTracker, messages: &[PitchMessage]) {
+        for message in messages {
+            tracker.observe(message).unwrap();
+        }
+    }
+    
+    #[test]
+    fn session_schedule_transitions_are_legal() {
+        let day = [Closed, PreMarket, PreOpen, Trading, MocTrading, PreClose, PostMarket, Closed];
+        assert!(day.windows(2).all(|pair| is_legal_transition(pair[0], pair[1])));
+        
+        // Halts interrupt anything and resume directly or through an auction
+        for from in [PreOpen, Trading, PreClose] {
+            assert!(is_legal_transition(from, Halted));
+        }
+        assert!(is_legal_transition(Halted, Trading));
+        assert!(is_legal_transition(Halted, PreOpen));
+        assert!(is_legal_transition(TradingSuspended, Halted));
+        
+        assert!(!is_legal_transition(Closed, Trading));
+        assert!(!is_legal_transition(Trading, PreOpen));
+        assert!(!is_legal_transition(PostMarket, Trading));
+        assert!(!is_legal_transition(Halted, PreMarket));
+    }
+    
+    #[test]
+    fn illegal_transitions_are_flagged_by_default() {
+        let mut tracker = StatusTracker::new(TransitionPolicy::Flag);
+        observe_all(&mut tracker, &[
+            status("AAPL", Trading, 0),
+            status("AAPL", Trading, 1), // Repeat, not a transition
+            status("AAPL", PreOpen, 2),
+            status("MSFT", PreOpen, 3),
+        ]);
+        
+        let transitions = tracker.transitions("AAPL");
+        assert_eq!(transitions.len(), 2);
+        assert_eq!(transitions[0].from, None);
+        assert!(transitions[0].legal);
+        assert_eq!((transitions[1].from, transitions[1].to, transitions[1].legal), (Some(Trading), PreOpen, false));
+        
+        let illegal: Vec<&str> = tracker.illegal_transitions().map(|transition| transition.symbol.as_str()).collect();
+        assert_eq!(illegal, vec!["AAPL"]);
+        assert_eq!(tracker.current_status("AAPL"), Some(PreOpen));
+    }
+    
+    #[test]
+    fn reject_policy_keeps_the_previous_status() {
+        let mut tracker = StatusTracker::new(TransitionPolicy::Reject);
+        tracker.observe(&status("AAPL", Closed, 0)).unwrap();
+        
+        let error = tracker.observe(&status("AAPL", Trading, 1)).unwrap_err();
+        
+        assert!(matches!(error, PitchError::IllegalTransition { from: Closed, to: Trading, .. }));
+        assert_eq!(tracker.current_status("AAPL"), Some(Closed));
+        assert_eq!(tracker.transitions("AAPL").len(), 1);
+        assert!(tracker.observe(&status("AAPL", PreOpen, 2)).unwrap().is_some());
+    }
+    
+    #[test]
+    fn status_at_answers_point_in_time_queries() {
+        let mut tracker = StatusTracker::default();
+        observe_all(&mut tracker, &[status("AAPL", PreOpen, 10), status("AAPL", Trading, 20)]);
+        
+        assert_eq!(tracker.status_at("AAPL", at(5)), None);
+        assert_eq!(tracker.status_at("AAPL", at(10)), Some(PreOpen));
+        assert_eq!(tracker.is_tradable_at("AAPL", at(15)), Some(false));
+        assert_eq!(tracker.is_tradable_at("AAPL", at(25)), Some(true));
+        assert_eq!(tracker.status_at("MSFT", at(25)), None);
+    }
+    
+    #[test]
+    fn halts_and_resumes_are_measured() {
+        let mut tracker = StatusTracker::default();
+        observe_all(&mut tracker, &[
+            status("AAPL", Trading, 0),
+            status("AAPL", Halted, 100),
+            status("AAPL", PreOpen, 130),
+            status("AAPL", Trading, 140),
+            status("AAPL", Halted, 200),
+            status("AAPL", TradingSuspended, 210),
+        ]);
+        
+        assert_eq!(tracker.halts("AAPL"), vec![
+            HaltPeriod { status: Halted, start: at(100), end: Some(at(130)) },
+            HaltPeriod { status: Halted, start: at(200), end: Some(at(210)) },
+            HaltPeriod { status: TradingSuspended, start: at(210), end: None },
+        ]);
+        assert_eq!(tracker.halted_duration("AAPL", Halted, at(300)), TimeDelta::seconds(40));
+        // The open suspension counts up to now
+        assert_eq!(tracker.halted_duration("AAPL", TradingSuspended, at(300)), TimeDelta::seconds(90));
+        assert!(tracker.transitions("AAPL").iter().all(|transition| transition.legal));
+    }
+}